@@ -5,9 +5,10 @@ use bytes::Bytes;
 
 use clap::{crate_authors, crate_version, App, Arg, ArgMatches, SubCommand};
 
-use tokio::net::UnixStream;
 use tokio::prelude::*;
 
+use cliff::{Endpoint, Socket};
+
 pub struct Connect {}
 
 impl Connect {
@@ -39,19 +40,30 @@ impl Connect {
                     .conflicts_with("interactive")
                     .help("Sends a single message and closes the connection"),
             )
+            .arg(
+                Arg::with_name("endpoint")
+                    .long("endpoint")
+                    .takes_value(true)
+                    .help("Where central station listens, as a socket path, `unix:<path>` or `tcp:<addr>`"),
+            )
     }
 }
 
-pub enum ConnectRunner {
+pub struct ConnectRunner {
+    endpoint: Option<String>,
+    mode: ConnectMode,
+}
+
+enum ConnectMode {
     Interactive,
     Message(String),
 }
 
 impl ConnectRunner {
     pub fn run(&self) -> io::Result<()> {
-        match self {
-            Self::Interactive => self.run_interactive(),
-            Self::Message(_) => self.run_message(),
+        match self.mode {
+            ConnectMode::Interactive => self.run_interactive(),
+            ConnectMode::Message(_) => self.run_message(),
         }
     }
 }
@@ -61,10 +73,7 @@ impl ConnectRunner {
     async fn run_message(&self) -> io::Result<()> {
         // 1. Connect to running instance of `central_station`
         //  - Get Socket Address
-        let home = std::env::var("HOME").map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-        let addr = format!("{}/.central/tmp/.modules", home);
-
-        let mut client = UnixStream::connect(addr).await.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        let (_, mut client) = self.connect().await?.into_split();
 
         // 2. Send stored message!
         let message = self.message_value().expect("Invariant Violation: Running message passing mode when no message is present");
@@ -87,10 +96,7 @@ impl ConnectRunner {
     async fn run_interactive(&self) -> io::Result<()> {
         // 1. Connect to running instance of `central_station`
         //  - Get Socket Address
-        let home = std::env::var("HOME").map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-        let addr = format!("{}/.central/tmp/.modules", home);
-
-        let mut client = UnixStream::connect(addr).await.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        let (_, mut client) = self.connect().await?.into_split();
 
         // 2. Send stored message!
         loop {
@@ -116,9 +122,16 @@ impl ConnectRunner {
         }
     }
 
+    async fn connect(&self) -> io::Result<Socket> {
+        let endpoint = Endpoint::resolve(self.endpoint.as_deref())
+            .map_err(|e| io::Error::other(e.compat()))?;
+
+        Socket::connect(&endpoint).await
+    }
+
     fn message_value(&self) -> Option<String> {
-        match self {
-            Self::Message(m) => Some(m.clone()),
+        match &self.mode {
+            ConnectMode::Message(m) => Some(m.clone()),
             _ => None,
         }
     }
@@ -126,10 +139,18 @@ impl ConnectRunner {
 
 impl<'a> From<&ArgMatches<'a>> for ConnectRunner {
     fn from(arg_matches: &ArgMatches<'a>) -> ConnectRunner {
+        let endpoint = arg_matches.value_of("endpoint").map(String::from);
+
         if arg_matches.is_present("interactive") {
-            return ConnectRunner::Interactive;
+            return ConnectRunner {
+                endpoint,
+                mode: ConnectMode::Interactive,
+            };
         }
 
-        ConnectRunner::Message(String::from(arg_matches.value_of("message").unwrap()))
+        ConnectRunner {
+            endpoint,
+            mode: ConnectMode::Message(String::from(arg_matches.value_of("message").unwrap())),
+        }
     }
 }
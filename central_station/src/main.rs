@@ -1,23 +1,27 @@
-use std::env::var;
+use std::env::args;
 use std::error::Error;
 use std::str;
 
 use bytes::BytesMut;
 
 use tokio::prelude::*;
-use tokio::net::UnixListener;
+
+use cliff::{Endpoint, Listener, LockFile};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let home = var("HOME")?;
+    // An endpoint passed as the only argument wins over the environment and config file
+    let explicit = args().nth(1);
+    let endpoint = Endpoint::resolve(explicit.as_deref()).map_err(|e| e.to_string())?;
 
-    // TODO: Create path if it doesn't exist yet
-    let mut listener = UnixListener::bind(format!("{}/.central/tmp/.modules", home))
-        .expect("Couldn't bind address");
+    // Held until the station exits, so a second one can't take over its socket
+    let _lock = LockFile::for_endpoint(&endpoint).map_err(|e| e.to_string())?;
+    let mut listener = Listener::bind(&endpoint).map_err(|e| e.to_string())?;
+    println!("Listening on {}", endpoint);
 
     let mut connection_count: u32 = 0;
     loop {
-        let (mut socket, _) = listener.accept().await?;
+        let (mut socket, _) = listener.accept().await?.into_split();
         let connection_id = connection_count;
         connection_count += 1;
 
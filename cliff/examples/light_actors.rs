@@ -1,9 +1,15 @@
 use std::collections::HashSet;
 
-use cliff::{Handler, Message, UnixConnection, UnixServer};
+use serde::{Deserialize, Serialize};
+
+use cliff::{
+    Actor, ConnectionId, ConnectionLost, Handler, Heartbeat, Message, Outbound, Registered,
+    Registry, StopMode, UnixConnection, UnixServer,
+};
 
 // Cat Shelter
 // Data
+#[derive(Serialize, Deserialize)]
 struct Cat {
     id: usize,
     name: String,
@@ -12,14 +18,14 @@ struct Cat {
 }
 
 // Client Messages
+#[derive(Message, Serialize, Deserialize)]
 struct Subscribe(usize);
-impl Message for Subscribe {}
 
+#[derive(Message, Serialize, Deserialize)]
 struct Unsubscribe(usize);
-impl Message for Unsubscribe {}
 
+#[derive(Message)]
 struct Boogey {}
-impl Message for Boogey {}
 //enum ClientMessage {
 //Subscribe,
 //Unsubscribe,
@@ -29,11 +35,11 @@ impl Message for Boogey {}
 //}
 
 // Responses
+#[derive(Message, Serialize, Deserialize)]
 struct Synchronize(Vec<Cat>);
-impl Message for Synchronize {}
 
+#[derive(Message, Serialize, Deserialize)]
 struct Update(Cat);
-impl Message for Update {}
 //enum ServerMessage {
 //Synch(Vec<Cat>),
 //Update(Cat),
@@ -43,12 +49,38 @@ impl Message for Update {}
 #[derive(Default)]
 struct Server {
     subscribers: HashSet<usize>,
+    clients: Vec<(ConnectionId, Outbound<Client>)>,
 }
 
-impl Handler<UnixConnection> for Server {
-    fn handle(&mut self, message: &mut UnixConnection) {
-        // TODO: Implement and store connection's read side
-        unimplemented!()
+impl Actor for Server {}
+
+impl Registered for Server {
+    fn registry() -> Registry<Self> {
+        let mut registry = Registry::new();
+        registry
+            .register::<Subscribe>(Subscribe::message_type())
+            .register::<Unsubscribe>(Unsubscribe::message_type());
+
+        registry
+    }
+
+    fn heartbeat() -> Option<Heartbeat> {
+        Some(Heartbeat::default())
+    }
+}
+
+impl Handler<UnixConnection<Client>> for Server {
+    fn handle(&mut self, message: &mut UnixConnection<Client>) {
+        let client = message.outbound().clone();
+        client.send(Synchronize(vec![])).ok();
+
+        self.clients.push((message.id(), client));
+    }
+}
+
+impl Handler<ConnectionLost> for Server {
+    fn handle(&mut self, message: &mut ConnectionLost) {
+        self.clients.retain(|(id, _)| *id != message.0);
     }
 }
 
@@ -64,14 +96,47 @@ impl Handler<Unsubscribe> for Server {
     }
 }
 
+#[derive(Registered)]
+#[handles(Synchronize, Update)]
+struct Client {
+    cats: Vec<Cat>,
+}
+
+impl Actor for Client {}
+
+impl Handler<Synchronize> for Client {
+    fn handle(&mut self, message: &mut Synchronize) {
+        self.cats = std::mem::take(&mut message.0);
+    }
+}
+
+impl Handler<Update> for Client {
+    fn handle(&mut self, message: &mut Update) {
+        let cat = std::mem::replace(
+            &mut message.0,
+            Cat {
+                id: 0,
+                name: String::new(),
+                has_owner: false,
+                aggressiveness: 0,
+            },
+        );
+
+        match self.cats.iter_mut().find(|known| known.id == cat.id) {
+            Some(known) => *known = cat,
+            None => self.cats.push(cat),
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() {
     // Spawn Server
     let runtime = Server::serve();
 
     // Example Message Sending
-    runtime.send(Subscribe(2));
-    runtime.send(Unsubscribe(2));
+    runtime.send(Subscribe(2)).await.unwrap();
+    runtime.send(Unsubscribe(2)).await.unwrap();
 
     // TODO: Actually write Server code
 
@@ -80,4 +145,6 @@ async fn main() {
 
     //tokio::signal::ctrl_c().await.ok();
     println!("Shutting down gracefully");
+    runtime.stop(StopMode::Drain);
+    runtime.join().await;
 }
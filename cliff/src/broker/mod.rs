@@ -0,0 +1,271 @@
+mod pattern;
+
+pub use pattern::Pattern;
+
+use serde::{Deserialize, Serialize};
+
+use crate::runtime::{
+    Actor, Address, Handled, Handler, MailboxError, Message, Request, Responder, SendError,
+};
+
+/// An event as it reaches a subscriber, along with the topic it was published to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Publication<E> {
+    pub topic: String,
+    pub event: E,
+}
+impl<E: Send + Sync> Message for Publication<E> {}
+
+/// Anything a `Broker` can fan events out to.
+pub trait Subscriber<E>: Send + Sync + 'static {
+    /// Hands `publication` over, returning `false` once the subscriber is gone for good.
+    fn deliver(&self, publication: Publication<E>) -> bool;
+}
+
+/// Actors subscribe through their `Address`.
+///
+/// Delivery never waits: an actor whose bounded mailbox is full misses the event.
+impl<T, E> Subscriber<E> for Address<T>
+where
+    T: 'static,
+    E: Send + Sync + 'static,
+    Publication<E>: Handled<T>,
+{
+    fn deliver(&self, publication: Publication<E>) -> bool {
+        match self.try_send(publication) {
+            Ok(()) => true,
+            Err(e) => !e.is_closed(),
+        }
+    }
+}
+
+/// Identifies a subscription, so it can be cancelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(usize);
+
+struct Subscription<E> {
+    id: SubscriptionId,
+    pattern: Pattern,
+    subscriber: Box<dyn Subscriber<E>>,
+}
+
+/// Fans events published to a topic out to every subscriber with a matching `Pattern`.
+///
+/// Subscribers that are gone are dropped the next time an event reaches them.
+///
+/// ```ignore
+/// let broker = Runtime::spawn(Broker::<Adoption>::default());
+/// broker.subscribe("cats.#", shelter.clone()).await?;
+/// broker.publish("cats.tom", Adoption::new()).await?;
+/// ```
+pub struct Broker<E> {
+    subscriptions: Vec<Subscription<E>>,
+    next_id: usize,
+}
+
+impl<E> Default for Broker<E> {
+    fn default() -> Self {
+        Self {
+            subscriptions: vec![],
+            next_id: 0,
+        }
+    }
+}
+
+impl<E: Send + 'static> Actor for Broker<E> {}
+
+pub struct Subscribe<E> {
+    pattern: Pattern,
+    subscriber: Option<Box<dyn Subscriber<E>>>,
+}
+
+impl<E> Subscribe<E> {
+    pub fn new<S: Subscriber<E>>(pattern: impl Into<Pattern>, subscriber: S) -> Self {
+        Self {
+            pattern: pattern.into(),
+            subscriber: Some(Box::new(subscriber)),
+        }
+    }
+}
+
+impl<E: 'static> Message for Subscribe<E> {}
+impl<E: 'static> Request for Subscribe<E> {
+    type Result = SubscriptionId;
+}
+
+impl<E: Send + 'static> Responder<Subscribe<E>> for Broker<E> {
+    fn respond(&mut self, message: &mut Subscribe<E>) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+
+        if let Some(subscriber) = message.subscriber.take() {
+            self.subscriptions.push(Subscription {
+                id,
+                pattern: message.pattern.clone(),
+                subscriber,
+            });
+        }
+
+        id
+    }
+}
+
+pub struct Unsubscribe(pub SubscriptionId);
+impl Message for Unsubscribe {}
+
+impl<E: Send + 'static> Handler<Unsubscribe> for Broker<E> {
+    fn handle(&mut self, message: &mut Unsubscribe) {
+        self.subscriptions
+            .retain(|subscription| subscription.id != message.0);
+    }
+}
+
+pub struct Publish<E> {
+    topic: String,
+    event: Option<E>,
+}
+
+impl<E> Publish<E> {
+    pub fn new(topic: &str, event: E) -> Self {
+        Self {
+            topic: topic.to_string(),
+            event: Some(event),
+        }
+    }
+}
+
+impl<E: Send + Sync> Message for Publish<E> {}
+
+impl<E: Clone + Send + Sync + 'static> Handler<Publish<E>> for Broker<E> {
+    fn handle(&mut self, message: &mut Publish<E>) {
+        let event = match message.event.take() {
+            Some(event) => event,
+            None => return,
+        };
+        let topic = &message.topic;
+
+        self.subscriptions.retain(|subscription| {
+            !subscription.pattern.matches(topic)
+                || subscription.subscriber.deliver(Publication {
+                    topic: topic.clone(),
+                    event: event.clone(),
+                })
+        });
+    }
+}
+
+/// Counts subscriptions, either all of them or only those matching a topic.
+pub struct CountSubscribers(pub Option<String>);
+impl Message for CountSubscribers {}
+impl Request for CountSubscribers {
+    type Result = usize;
+}
+
+impl<E: Send + 'static> Responder<CountSubscribers> for Broker<E> {
+    fn respond(&mut self, message: &mut CountSubscribers) -> usize {
+        match &message.0 {
+            Some(topic) => self
+                .subscriptions
+                .iter()
+                .filter(|subscription| subscription.pattern.matches(topic))
+                .count(),
+            None => self.subscriptions.len(),
+        }
+    }
+}
+
+impl<E: Clone + Send + Sync + 'static> Address<Broker<E>> {
+    pub async fn subscribe<S: Subscriber<E>>(
+        &self,
+        pattern: impl Into<Pattern>,
+        subscriber: S,
+    ) -> Result<SubscriptionId, MailboxError> {
+        self.ask(Subscribe::new(pattern, subscriber)).await
+    }
+
+    pub async fn unsubscribe(&self, id: SubscriptionId) -> Result<(), SendError<Unsubscribe>> {
+        self.send(Unsubscribe(id)).await
+    }
+
+    pub async fn publish(&self, topic: &str, event: E) -> Result<(), SendError<Publish<E>>> {
+        self.send(Publish::new(topic, event)).await
+    }
+
+    /// Counts the subscribers `topic` would reach, or every subscriber without one.
+    pub async fn subscribers(&self, topic: Option<&str>) -> Result<usize, MailboxError> {
+        self.ask(CountSubscribers(topic.map(str::to_string))).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        runtime::{Runtime, StopMode},
+        testing::{Recorded, Recorder},
+    };
+
+    type Inbox = Recorder<Publication<u32>>;
+
+    async fn received(inbox: &Runtime<Inbox>) -> Vec<(String, u32)> {
+        let publications = inbox.ask(Recorded::new()).await.unwrap();
+
+        publications
+            .into_iter()
+            .map(|publication| (publication.topic, publication.event))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn fans_out_to_matching_subscribers() {
+        let broker = Runtime::spawn(Broker::<u32>::default());
+        let cats = Runtime::spawn(Inbox::default());
+        let everything = Runtime::spawn(Inbox::default());
+
+        broker.subscribe("cats.*", (*cats).clone()).await.unwrap();
+        broker.subscribe("#", (*everything).clone()).await.unwrap();
+
+        broker.publish("cats.tom", 1).await.unwrap();
+        broker.publish("dogs.rex", 2).await.unwrap();
+        // Asking the broker makes sure both events went out
+        assert_eq!(broker.subscribers(None).await.unwrap(), 2);
+
+        assert_eq!(received(&cats).await, vec![("cats.tom".to_string(), 1)]);
+        assert_eq!(
+            received(&everything).await,
+            vec![("cats.tom".to_string(), 1), ("dogs.rex".to_string(), 2)]
+        );
+    }
+
+    #[tokio::test]
+    async fn unsubscribed_subscribers_stop_receiving() {
+        let broker = Runtime::spawn(Broker::<u32>::default());
+        let inbox = Runtime::spawn(Inbox::default());
+
+        let id = broker.subscribe("cats", (*inbox).clone()).await.unwrap();
+        broker.publish("cats", 1).await.unwrap();
+        broker.unsubscribe(id).await.unwrap();
+        broker.publish("cats", 2).await.unwrap();
+
+        assert_eq!(broker.subscribers(Some("cats")).await.unwrap(), 0);
+        assert_eq!(received(&inbox).await, vec![("cats".to_string(), 1)]);
+    }
+
+    #[tokio::test]
+    async fn drops_dead_subscribers() {
+        let broker = Runtime::spawn(Broker::<u32>::default());
+        let alive = Runtime::spawn(Inbox::default());
+        let dead = Runtime::spawn(Inbox::default());
+
+        broker.subscribe("cats.#", (*alive).clone()).await.unwrap();
+        broker.subscribe("cats.#", (*dead).clone()).await.unwrap();
+
+        dead.stop(StopMode::Discard);
+        dead.join().await;
+
+        assert_eq!(broker.subscribers(Some("cats.tom")).await.unwrap(), 2);
+        broker.publish("cats.tom", 1).await.unwrap();
+        assert_eq!(broker.subscribers(Some("cats.tom")).await.unwrap(), 1);
+    }
+}
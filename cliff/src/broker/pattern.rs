@@ -0,0 +1,146 @@
+use std::{fmt, iter};
+
+/// A dot separated topic pattern, such as `cats.*.adopted` or `cats.#`.
+///
+/// `*` matches exactly one segment and `#` matches any number of them, none included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    raw: String,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Exact(String),
+    One,
+    Any,
+}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Self {
+        let segments = pattern
+            .split('.')
+            .map(|segment| match segment {
+                "*" => Segment::One,
+                "#" => Segment::Any,
+                exact => Segment::Exact(exact.to_string()),
+            })
+            .collect();
+
+        Self {
+            raw: pattern.to_string(),
+            segments,
+        }
+    }
+
+    pub fn matches(&self, topic: &str) -> bool {
+        let topic = topic.split('.').collect::<Vec<_>>();
+
+        matches(&self.segments, &topic)
+    }
+}
+
+/// Walks the pattern once, keeping track of the topic prefixes it matches so far.
+///
+/// Takes `pattern.len() * topic.len()` steps whatever the wildcards, topics come from peers.
+fn matches(pattern: &[Segment], topic: &[&str]) -> bool {
+    // `matched[i]` tells whether the segments walked so far match the first `i` of the topic
+    let mut matched = vec![false; topic.len() + 1];
+    matched[0] = true;
+
+    for segment in pattern {
+        matched = match segment {
+            Segment::Any => matched
+                .iter()
+                .scan(false, |reached, &prefix| {
+                    *reached |= prefix;
+                    Some(*reached)
+                })
+                .collect(),
+            segment => iter::once(false)
+                .chain(
+                    matched
+                        .iter()
+                        .zip(topic)
+                        .map(|(&prefix, next)| prefix && segment.accepts(next)),
+                )
+                .collect(),
+        };
+    }
+
+    matched[topic.len()]
+}
+
+impl Segment {
+    fn accepts(&self, segment: &str) -> bool {
+        match self {
+            Segment::Exact(exact) => exact == segment,
+            Segment::One | Segment::Any => true,
+        }
+    }
+}
+
+impl From<&str> for Pattern {
+    fn from(pattern: &str) -> Self {
+        Self::new(pattern)
+    }
+}
+
+impl From<String> for Pattern {
+    fn from(pattern: String) -> Self {
+        Self::new(&pattern)
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_exact_topics() {
+        let pattern = Pattern::new("cats.adopted");
+
+        assert!(pattern.matches("cats.adopted"));
+        assert!(!pattern.matches("cats"));
+        assert!(!pattern.matches("cats.adopted.today"));
+    }
+
+    #[test]
+    fn star_matches_one_segment() {
+        let pattern = Pattern::new("cats.*.adopted");
+
+        assert!(pattern.matches("cats.tom.adopted"));
+        assert!(!pattern.matches("cats.adopted"));
+        assert!(!pattern.matches("cats.tom.jerry.adopted"));
+    }
+
+    #[test]
+    fn hash_matches_any_segments() {
+        let pattern = Pattern::new("cats.#");
+
+        assert!(pattern.matches("cats"));
+        assert!(pattern.matches("cats.tom"));
+        assert!(pattern.matches("cats.tom.adopted"));
+        assert!(!pattern.matches("dogs.rex"));
+
+        assert!(Pattern::new("#.adopted").matches("cats.tom.adopted"));
+        assert!(Pattern::new("cats.#.#.adopted").matches("cats.adopted"));
+        assert!(Pattern::new("#.tom.#").matches("cats.tom.adopted"));
+        assert!(!Pattern::new("#.tom.#").matches("cats.jerry.adopted"));
+    }
+
+    #[test]
+    fn many_hashes_match_long_topics_quickly() {
+        let topic = ["cat"; 200].join(".");
+
+        assert!(Pattern::new("#.cat.#.#.*.#.#.#.#.cat.#").matches(&topic));
+        assert!(!Pattern::new("#.#.#.#.#.#.#.#.dog").matches(&topic));
+        assert!(!Pattern::new(&["*"; 201].join(".")).matches(&topic));
+    }
+}
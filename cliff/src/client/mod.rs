@@ -0,0 +1,898 @@
+mod pending;
+mod reconnect;
+mod remote;
+
+pub use reconnect::{ConnectionState, Reconnect};
+pub use remote::RemoteAddress;
+
+use std::{
+    collections::VecDeque,
+    env, io,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use failure::{format_err, Error};
+
+use futures::{
+    sink::SinkExt,
+    stream::{Stream, StreamExt},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use tokio::{
+    sync::{
+        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+        watch,
+    },
+    time,
+};
+
+use tokio_util::codec::{FramedRead, FramedWrite};
+
+use crate::{
+    codec::{
+        from_value, to_value, Beat, Capabilities, Capability, Control, Envelope, Heartbeat,
+        Identity, Liveness, MsgPackCodec, Negotiated, Registered, Secret, HANDSHAKE_MAX_FRAME,
+        PROTOCOL_VERSION,
+    },
+    runtime::{Actor, Address, Handled, SendError},
+    transport::{Endpoint, ReadHalf, Socket, WriteHalf},
+};
+
+use pending::Pending;
+
+/// How long requests wait on their reply, unless the `ClientConfig` says otherwise.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Connects to the local server.
+///
+/// `Out` is what the client sends and `In` what it expects back, both going
+/// through rmp-serde on the way, structs as maps keyed by field name.
+pub async fn create_client<Out, In>() -> Result<Client<Out, In>, Error>
+where
+    Out: Serialize,
+    In: DeserializeOwned,
+{
+    create_client_with(ClientConfig::new()).await
+}
+
+/// Connects to the local server in the background, getting back in touch
+/// whenever the connection is lost, as `policy` describes.
+pub fn create_reconnecting_client<Out, In>(policy: Reconnect) -> Result<Client<Out, In>, Error>
+where
+    Out: Serialize,
+    In: DeserializeOwned,
+{
+    Ok(Client::start(
+        None,
+        Endpoint::local()?,
+        ClientConfig::new().with_reconnect(policy),
+    ))
+}
+
+/// Connects as `config` describes, to the local server unless it names an `Endpoint`.
+///
+/// Only fails to connect right away without a `Reconnect` policy.
+pub async fn create_client_with<Out, In>(config: ClientConfig) -> Result<Client<Out, In>, Error>
+where
+    Out: Serialize,
+    In: DeserializeOwned,
+{
+    let endpoint = match &config.endpoint {
+        Some(endpoint) => endpoint.clone(),
+        None => Endpoint::local()?,
+    };
+    let channel = match config.reconnect {
+        Some(_) => None,
+        None => Some(Channel::open(&endpoint, &config.identity()).await?),
+    };
+
+    Ok(Client::start(channel, endpoint, config))
+}
+
+/// How a client keeps its connection to the server going.
+#[derive(Debug, Clone, Default)]
+pub struct ClientConfig {
+    endpoint: Option<Endpoint>,
+    name: Option<String>,
+    secret: Option<Secret>,
+    reconnect: Option<Reconnect>,
+    heartbeat: Option<Heartbeat>,
+    request_timeout: Option<Duration>,
+}
+
+impl ClientConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Connects to `endpoint` instead of the local server.
+    pub fn with_endpoint(mut self, endpoint: Endpoint) -> Self {
+        self.endpoint = Some(endpoint);
+
+        self
+    }
+
+    /// How the client introduces itself to the server, its executable's name by default.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+
+        self
+    }
+
+    /// Proves the client may connect to servers that require `secret`.
+    pub fn with_secret(mut self, secret: Secret) -> Self {
+        self.secret = Some(secret);
+
+        self
+    }
+
+    pub fn with_reconnect(mut self, policy: Reconnect) -> Self {
+        self.reconnect = Some(policy);
+
+        self
+    }
+
+    /// Pings the server as `heartbeat` describes, dropping the connection once it goes silent.
+    pub fn with_heartbeat(mut self, heartbeat: Heartbeat) -> Self {
+        self.heartbeat = Some(heartbeat);
+
+        self
+    }
+
+    /// How long requests wait on their reply, `DEFAULT_REQUEST_TIMEOUT` otherwise.
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+
+        self
+    }
+
+    fn identity(&self) -> Identity {
+        let name = self.name.clone().or_else(|| {
+            let executable = env::current_exe().ok()?;
+
+            Some(executable.file_stem()?.to_string_lossy().into_owned())
+        });
+
+        Identity::new(
+            name.unwrap_or_else(|| "cliff".to_string()),
+            self.secret.clone(),
+        )
+    }
+}
+
+/// Both halves of a connection that made it past the handshake.
+struct Channel {
+    reader: FramedRead<ReadHalf, MsgPackCodec>,
+    writer: FramedWrite<WriteHalf, MsgPackCodec>,
+    negotiated: Negotiated,
+}
+
+impl Channel {
+    async fn open(endpoint: &Endpoint, identity: &Identity) -> Result<Self, Error> {
+        let mut channel = Self::from(Socket::connect(endpoint).await?);
+
+        // Only handshake sized frames are buffered until the server is known
+        *channel.reader.decoder_mut() = MsgPackCodec::new().with_max_frame(HANDSHAKE_MAX_FRAME);
+        channel.negotiated = identity
+            .introduce(&mut channel.reader, &mut channel.writer)
+            .await?;
+        *channel.reader.decoder_mut() = MsgPackCodec::new();
+
+        Ok(channel)
+    }
+}
+
+// Assumes the newest protocol until a handshake says otherwise
+impl From<Socket> for Channel {
+    fn from(socket: Socket) -> Self {
+        let (read_half, write_half) = socket.into_split();
+
+        Self {
+            reader: FramedRead::new(read_half, MsgPackCodec::new()),
+            writer: FramedWrite::new(write_half, MsgPackCodec::new()),
+            negotiated: Negotiated {
+                version: PROTOCOL_VERSION,
+                capabilities: Capabilities::all(),
+            },
+        }
+    }
+}
+
+type Observer = Box<dyn Fn(ConnectionState) + Send + Sync>;
+
+/// Both ends of a connection to a server.
+///
+/// The connection closes once the client and every `ClientSink` split off of it
+/// are dropped, or once the server goes away for a client that doesn't reconnect.
+pub struct Client<Out, In> {
+    sink: ClientSink<Out>,
+    stream: ClientStream<In>,
+    observers: Arc<Mutex<Vec<Observer>>>,
+    pending: Pending,
+}
+
+impl<Out: Serialize, In: DeserializeOwned> Client<Out, In> {
+    fn start(channel: Option<Channel>, endpoint: Endpoint, config: ClientConfig) -> Self {
+        let (outgoing, to_write) = unbounded_channel();
+        let (incoming, received) = unbounded_channel();
+        let initial = match channel {
+            Some(_) => ConnectionState::Connected,
+            None => ConnectionState::Disconnected,
+        };
+        let (state, link) = watch::channel(initial);
+        let observers = Arc::new(Mutex::new(vec![]));
+        let capacity = config.reconnect.as_ref().and_then(Reconnect::outbox);
+        let pending = Pending::new(config.request_timeout.unwrap_or(DEFAULT_REQUEST_TIMEOUT));
+
+        let driver = Driver {
+            identity: config.identity(),
+            endpoint,
+            reconnect: config.reconnect,
+            heartbeat: config.heartbeat,
+            to_write,
+            incoming,
+            state,
+            observers: observers.clone(),
+            pending: pending.clone(),
+            outbox: VecDeque::new(),
+            capacity,
+        };
+        tokio::spawn(driver.run(channel));
+
+        Self {
+            sink: ClientSink {
+                outgoing,
+                link: Link(link),
+                buffered: capacity.is_some(),
+                message: PhantomData,
+            },
+            stream: ClientStream {
+                received,
+                message: PhantomData,
+            },
+            observers,
+            pending,
+        }
+    }
+
+    /// Queues `message` to be written to the server.
+    pub fn send(&self, message: Out) -> Result<(), Error> {
+        self.sink.send(message)
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.sink.state()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.sink.is_connected()
+    }
+
+    /// Resolves once the connection is lost.
+    pub async fn disconnected(&self) {
+        self.sink.disconnected().await
+    }
+
+    /// Reports every change of `ConnectionState` to `observer`.
+    pub fn notify<A: Actor>(&self, observer: Address<A>)
+    where
+        ConnectionState: Handled<A>,
+    {
+        let notify = move |state| {
+            // Only wait on the observer's mailbox when it's full, keeping states in order otherwise
+            if let Err(SendError::Full(state)) = observer.try_send(state) {
+                let observer = observer.clone();
+
+                tokio::spawn(async move {
+                    observer.send(state).await.ok();
+                });
+            }
+        };
+
+        lock(&self.observers).push(Box::new(notify));
+    }
+
+    /// Sends `message` as a request, resolving with the reply the server matched to it.
+    ///
+    /// Fails with `MailboxError::TimedOut` once the request timeout passes,
+    /// and with `MailboxError::Closed` if the connection drops first.
+    pub async fn request(&self, message: Out) -> Result<In, Error> {
+        let reply = self.pending.ask(&self.sink, to_value(&message)?).await?;
+
+        from_value(&reply)
+    }
+
+    /// Reaches the server side actor `T` through this connection, `ask`s included.
+    pub fn address<T: Registered + 'static>(&self) -> RemoteAddress<T> {
+        RemoteAddress::new(self.sink.retype(), self.pending.clone())
+    }
+
+    pub fn split(self) -> (ClientSink<Out>, ClientStream<In>) {
+        (self.sink, self.stream)
+    }
+}
+
+impl<Out, In: DeserializeOwned> Stream for Client<Out, In> {
+    type Item = Result<In, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.stream).poll_next(cx)
+    }
+}
+
+fn lock(observers: &Mutex<Vec<Observer>>) -> std::sync::MutexGuard<'_, Vec<Observer>> {
+    observers.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+enum Ended {
+    /// Every sink is gone, so the client is done.
+    Closed,
+    Lost,
+}
+
+/// Owns the socket, moving values between it and the client's channels.
+struct Driver {
+    endpoint: Endpoint,
+    identity: Identity,
+    reconnect: Option<Reconnect>,
+    heartbeat: Option<Heartbeat>,
+    to_write: UnboundedReceiver<rmpv::Value>,
+    incoming: UnboundedSender<rmpv::Value>,
+    state: watch::Sender<ConnectionState>,
+    observers: Arc<Mutex<Vec<Observer>>>,
+    pending: Pending,
+    outbox: VecDeque<rmpv::Value>,
+    capacity: Option<usize>,
+}
+
+impl Driver {
+    async fn run(mut self, mut channel: Option<Channel>) {
+        if channel.is_none() && self.reconnect.is_some() {
+            channel = Channel::open(&self.endpoint, &self.identity).await.ok();
+        }
+
+        let mut attempt: u32 = 0;
+        loop {
+            if let Some(channel) = channel.take() {
+                attempt = 0;
+                self.report(ConnectionState::Connected);
+
+                let ended = self.session(channel).await;
+                self.pending.disconnected();
+                if let Ended::Closed = ended {
+                    return;
+                }
+                self.report(ConnectionState::Disconnected);
+            }
+
+            let delay = match &self.reconnect {
+                Some(policy) => policy.delay(attempt),
+                None => return,
+            };
+            attempt = attempt.saturating_add(1);
+            self.report(ConnectionState::Reconnecting { attempt, delay });
+
+            if let Ended::Closed = self.wait(delay).await {
+                return;
+            }
+            channel = Channel::open(&self.endpoint, &self.identity).await.ok();
+        }
+    }
+
+    async fn session(&mut self, channel: Channel) -> Ended {
+        let Channel {
+            mut reader,
+            mut writer,
+            negotiated,
+        } = channel;
+
+        while let Some(value) = self.outbox.front() {
+            // Requests that timed out while queued aren't worth replaying
+            if let Some(value) = self.pending.replayable(value.clone()) {
+                if writer.send(value).await.is_err() {
+                    return Ended::Lost;
+                }
+            }
+            self.outbox.pop_front();
+        }
+
+        let outbox = &mut self.outbox;
+        let capacity = self.capacity;
+        let incoming = &self.incoming;
+        let pending = &self.pending;
+        let capabilities = negotiated.capabilities;
+        let heartbeat = self
+            .heartbeat
+            .filter(|_| capabilities.contains(Capability::Heartbeat));
+        let mut liveness = Liveness::new(heartbeat);
+        loop {
+            tokio::select! {
+                value = self.to_write.recv() => match value {
+                    Some(value) => {
+                        if writer.send(value.clone()).await.is_err() {
+                            queue(outbox, capacity, value);
+                            return Ended::Lost;
+                        }
+                    }
+                    None => {
+                        writer.close().await.ok();
+                        return Ended::Closed;
+                    }
+                },
+                frame = reader.next() => {
+                    let value = match frame {
+                        Some(Ok(value)) => value,
+                        // The socket itself failed, nothing more will come through
+                        Some(Err(e)) if e.downcast_ref::<io::Error>().is_some() => {
+                            return Ended::Lost;
+                        }
+                        Some(Err(e)) => {
+                            eprintln!("Skipping a frame that couldn't be decoded: {}", e);
+                            continue;
+                        }
+                        None => return Ended::Lost,
+                    };
+                    liveness.saw_frame();
+
+                    match Control::from_value(&value) {
+                        Some(Control::Ping) => {
+                            if writer.send(Control::Pong.to_value()).await.is_err() {
+                                return Ended::Lost;
+                            }
+                        }
+                        Some(Control::Pong) => {}
+                        None => match Envelope::from_value(&value) {
+                            Some(reply) => pending.resolve(reply),
+                            None => {
+                                // The client may only care about sending
+                                incoming.send(value).ok();
+                            }
+                        },
+                    }
+                },
+                beat = liveness.tick() => match beat {
+                    Beat::Ping => {
+                        if writer.send(Control::Ping.to_value()).await.is_err() {
+                            return Ended::Lost;
+                        }
+                    }
+                    Beat::Dead => return Ended::Lost,
+                },
+            }
+        }
+    }
+
+    /// Holds on to whatever is sent while disconnected.
+    async fn wait(&mut self, delay: Duration) -> Ended {
+        let mut retry = time::delay_for(delay);
+
+        loop {
+            tokio::select! {
+                _ = &mut retry => return Ended::Lost,
+                value = self.to_write.recv() => match value {
+                    Some(value) => queue(&mut self.outbox, self.capacity, value),
+                    None => return Ended::Closed,
+                },
+            }
+        }
+    }
+
+    fn report(&self, state: ConnectionState) {
+        self.state.broadcast(state.clone()).ok();
+
+        for observer in lock(&self.observers).iter() {
+            observer(state.clone());
+        }
+    }
+}
+
+fn queue(outbox: &mut VecDeque<rmpv::Value>, capacity: Option<usize>, value: rmpv::Value) {
+    // Without an outbox, messages sent while disconnected are lost
+    if let Some(capacity) = capacity {
+        if outbox.len() >= capacity {
+            outbox.pop_front();
+        }
+        outbox.push_back(value);
+    }
+}
+
+#[derive(Clone)]
+struct Link(watch::Receiver<ConnectionState>);
+
+impl Link {
+    fn state(&self) -> ConnectionState {
+        self.0.borrow().clone()
+    }
+
+    async fn closed(&mut self) {
+        while let Some(ConnectionState::Connected) = self.0.recv().await {}
+    }
+}
+
+/// The sending half of a `Client`.
+pub struct ClientSink<Out> {
+    outgoing: UnboundedSender<rmpv::Value>,
+    link: Link,
+    buffered: bool,
+    message: PhantomData<fn(Out)>,
+}
+
+impl<Out> Clone for ClientSink<Out> {
+    fn clone(&self) -> Self {
+        Self {
+            outgoing: self.outgoing.clone(),
+            link: self.link.clone(),
+            buffered: self.buffered,
+            message: PhantomData,
+        }
+    }
+}
+
+impl<Out> ClientSink<Out> {
+    /// The same sink, sending another type of message.
+    fn retype<Other>(&self) -> ClientSink<Other> {
+        ClientSink {
+            outgoing: self.outgoing.clone(),
+            link: self.link.clone(),
+            buffered: self.buffered,
+            message: PhantomData,
+        }
+    }
+
+    fn send_value(&self, value: rmpv::Value) -> Result<(), Error> {
+        if !self.buffered && !self.is_connected() {
+            return Err(format_err!("The client isn't connected"));
+        }
+
+        self.outgoing
+            .send(value)
+            .map_err(|_| format_err!("The client is closed"))
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.link.state()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state() == ConnectionState::Connected
+    }
+
+    pub async fn disconnected(&self) {
+        self.link.clone().closed().await
+    }
+}
+
+impl<Out: Serialize> ClientSink<Out> {
+    /// Fails while disconnected, unless the client keeps an outbox, and once the client is
+    /// closed, or if `message` can't be serialized.
+    pub fn send(&self, message: Out) -> Result<(), Error> {
+        self.send_value(to_value(&message)?)
+    }
+}
+
+/// The receiving half of a `Client`, ending once the connection closes.
+///
+/// Values that can't be deserialized to `In` come through as errors.
+pub struct ClientStream<In> {
+    received: UnboundedReceiver<rmpv::Value>,
+    message: PhantomData<fn() -> In>,
+}
+
+impl<In: DeserializeOwned> Stream for ClientStream<In> {
+    type Item = Result<In, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.received
+            .poll_recv(cx)
+            .map(|value| value.map(|value| from_value(&value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    use tokio::net::{TcpListener, UnixListener, UnixStream};
+
+    use serde::Deserialize;
+
+    use tokio_util::codec::Framed;
+
+    use crate::{
+        codec::Gate,
+        runtime::{MailboxError, Runtime},
+        testing::{Recorded, Recorder, TempPath},
+    };
+
+    fn connected(socket: UnixStream) -> Client<rmpv::Value, rmpv::Value> {
+        Client::start(
+            Some(Socket::from(socket).into()),
+            nowhere(),
+            ClientConfig::new(),
+        )
+    }
+
+    /// Lets a client in the way a server without a secret would.
+    async fn admit(socket: Socket) -> Channel {
+        let mut channel = Channel::from(socket);
+        Gate::new(None, true)
+            .admit(&mut channel.reader, &mut channel.writer)
+            .await
+            .unwrap();
+
+        channel
+    }
+
+    // Clients handed a socket up front never connect on their own
+    fn nowhere() -> Endpoint {
+        Endpoint::Unix(PathBuf::new())
+    }
+
+    #[tokio::test]
+    async fn sends_and_receives_values() {
+        let (client_side, server_side) = UnixStream::pair().unwrap();
+        let mut client = connected(client_side);
+        let mut server = Framed::new(server_side, MsgPackCodec::new());
+
+        client.send(rmpv::Value::from("ping")).unwrap();
+        let received = server.next().await.unwrap().unwrap();
+        assert_eq!(received, rmpv::Value::from("ping"));
+
+        server.send(rmpv::Value::from("pong")).await.unwrap();
+        let reply = client.next().await.unwrap().unwrap();
+        assert_eq!(reply, rmpv::Value::from("pong"));
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Guess {
+        client_id: u16,
+        guess: u8,
+    }
+
+    #[tokio::test]
+    async fn sends_and_receives_typed_messages() {
+        let (client_side, server_side) = UnixStream::pair().unwrap();
+        let mut client = Client::<Guess, Guess>::start(
+            Some(Socket::from(client_side).into()),
+            nowhere(),
+            ClientConfig::new(),
+        );
+        let mut server = Framed::new(server_side, MsgPackCodec::new());
+
+        let guess = Guess {
+            client_id: 1,
+            guess: 7,
+        };
+        client.send(guess).unwrap();
+        let received = server.next().await.unwrap().unwrap();
+        assert_eq!(received["guess"], rmpv::Value::from(7));
+
+        server.send(received).await.unwrap();
+        server.send(rmpv::Value::from("nope")).await.unwrap();
+        assert_eq!(
+            client.next().await.unwrap().unwrap(),
+            Guess {
+                client_id: 1,
+                guess: 7
+            }
+        );
+        assert!(client.next().await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn reports_disconnection() {
+        let (client_side, server_side) = UnixStream::pair().unwrap();
+        let mut client = connected(client_side);
+
+        drop(server_side);
+
+        client.disconnected().await;
+        assert!(!client.is_connected());
+        assert!(client.next().await.is_none());
+        assert!(client.send(rmpv::Value::from("ping")).is_err());
+    }
+
+    #[tokio::test]
+    async fn connects_over_tcp() {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = Endpoint::Tcp(listener.local_addr().unwrap());
+
+        let config = ClientConfig::new().with_endpoint(endpoint);
+        let (client, server) = tokio::join!(
+            create_client_with::<rmpv::Value, rmpv::Value>(config),
+            async { admit(listener.accept().await.unwrap().0.into()).await }
+        );
+        let (mut client, mut server) = (client.unwrap(), server);
+
+        client.send(rmpv::Value::from("ping")).unwrap();
+        assert_eq!(
+            server.reader.next().await.unwrap().unwrap(),
+            rmpv::Value::from("ping")
+        );
+
+        server.writer.send(rmpv::Value::from("pong")).await.unwrap();
+        assert_eq!(
+            client.next().await.unwrap().unwrap(),
+            rmpv::Value::from("pong")
+        );
+    }
+
+    #[tokio::test]
+    async fn closes_once_dropped() {
+        let (client_side, server_side) = UnixStream::pair().unwrap();
+        let client = connected(client_side);
+        let mut server = Framed::new(server_side, MsgPackCodec::new());
+
+        drop(client);
+
+        assert!(server.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn reconnects_and_replays_the_outbox() {
+        let path = TempPath::new("reconnect.sock");
+        let mut listener = UnixListener::bind(&path).unwrap();
+
+        let policy = Reconnect::new()
+            .with_backoff(Duration::from_millis(10), Duration::from_millis(50))
+            .with_outbox(8);
+        let config = ClientConfig::new().with_reconnect(policy);
+        let endpoint = Endpoint::Unix(path.to_path_buf());
+        let client = Client::<rmpv::Value, rmpv::Value>::start(None, endpoint, config);
+        let states = Runtime::spawn(Recorder::<ConnectionState>::default());
+        client.notify((*states).clone());
+
+        let first = admit(listener.accept().await.unwrap().0.into()).await;
+        drop(first);
+        client.disconnected().await;
+
+        client.send(rmpv::Value::from("queued")).unwrap();
+
+        let mut second = admit(listener.accept().await.unwrap().0.into()).await;
+        let replayed = second.reader.next().await.unwrap().unwrap();
+        assert_eq!(replayed, rmpv::Value::from("queued"));
+
+        let seen = states.ask(Recorded::new()).await.unwrap();
+        assert!(seen.contains(&ConnectionState::Disconnected));
+        assert!(seen
+            .iter()
+            .any(|state| matches!(state, ConnectionState::Reconnecting { attempt: 1, .. })));
+        assert_eq!(seen.last(), Some(&ConnectionState::Connected));
+    }
+
+    #[tokio::test]
+    async fn replays_only_requests_still_waited_on() {
+        let path = TempPath::new("replay.sock");
+        let mut listener = UnixListener::bind(&path).unwrap();
+
+        let policy = Reconnect::new()
+            .with_backoff(Duration::from_millis(200), Duration::from_millis(200))
+            .with_outbox(8);
+        let config = ClientConfig::new()
+            .with_reconnect(policy)
+            .with_request_timeout(Duration::from_millis(20));
+        let endpoint = Endpoint::Unix(path.to_path_buf());
+        let client = Client::<rmpv::Value, rmpv::Value>::start(None, endpoint, config);
+
+        let first = admit(listener.accept().await.unwrap().0.into()).await;
+        drop(first);
+        // Requests sent before this would be failed along with the first connection
+        while !matches!(client.state(), ConnectionState::Reconnecting { .. }) {
+            time::delay_for(Duration::from_millis(1)).await;
+        }
+
+        let abandoned = client.request(rmpv::Value::from("abandoned")).await;
+        assert_eq!(
+            abandoned.unwrap_err().downcast_ref::<MailboxError>(),
+            Some(&MailboxError::TimedOut)
+        );
+        client.send(rmpv::Value::from("queued")).unwrap();
+
+        let mut second = admit(listener.accept().await.unwrap().0.into()).await;
+        let replayed = second.reader.next().await.unwrap().unwrap();
+        assert_eq!(replayed, rmpv::Value::from("queued"));
+    }
+
+    #[tokio::test]
+    async fn answers_pings_without_surfacing_them() {
+        let (client_side, server_side) = UnixStream::pair().unwrap();
+        let mut client = connected(client_side);
+        let mut server = Framed::new(server_side, MsgPackCodec::new());
+
+        server.send(Control::Ping.to_value()).await.unwrap();
+        server.send(rmpv::Value::from("after")).await.unwrap();
+
+        assert_eq!(
+            server.next().await.unwrap().unwrap(),
+            Control::Pong.to_value()
+        );
+        assert_eq!(
+            client.next().await.unwrap().unwrap(),
+            rmpv::Value::from("after")
+        );
+    }
+
+    #[tokio::test]
+    async fn drops_silent_servers() {
+        let (client_side, server_side) = UnixStream::pair().unwrap();
+        let heartbeat = Heartbeat::new(Duration::from_millis(10), Duration::from_millis(30));
+        let config = ClientConfig::new().with_heartbeat(heartbeat);
+        let client = Client::<rmpv::Value, rmpv::Value>::start(
+            Some(Socket::from(client_side).into()),
+            nowhere(),
+            config,
+        );
+        let mut server = Framed::new(server_side, MsgPackCodec::new());
+
+        assert_eq!(
+            server.next().await.unwrap().unwrap(),
+            Control::Ping.to_value()
+        );
+
+        let lost = time::timeout(Duration::from_secs(1), client.disconnected()).await;
+        assert!(lost.is_ok());
+        assert!(!client.is_connected());
+    }
+
+    #[tokio::test]
+    async fn matches_replies_to_their_requests() {
+        let (client_side, server_side) = UnixStream::pair().unwrap();
+        let client = connected(client_side);
+        let mut server = Framed::new(server_side, MsgPackCodec::new());
+
+        let answer = async {
+            let first = Envelope::from_value(&server.next().await.unwrap().unwrap()).unwrap();
+            let second = Envelope::from_value(&server.next().await.unwrap().unwrap()).unwrap();
+            assert!(first.deadline.is_some());
+
+            // Answered out of order
+            for request in [second, first].iter() {
+                let reply = Envelope::reply(request.id, request.body.clone());
+                server.send(reply.to_value()).await.unwrap();
+            }
+        };
+        let (first, second, _) = tokio::join!(
+            client.request(rmpv::Value::from("first")),
+            client.request(rmpv::Value::from("second")),
+            answer
+        );
+
+        assert_eq!(first.unwrap(), rmpv::Value::from("first"));
+        assert_eq!(second.unwrap(), rmpv::Value::from("second"));
+    }
+
+    #[tokio::test]
+    async fn requests_fail_past_their_deadline_or_connection() {
+        let (client_side, server_side) = UnixStream::pair().unwrap();
+        let config = ClientConfig::new().with_request_timeout(Duration::from_millis(20));
+        let client = Client::<rmpv::Value, rmpv::Value>::start(
+            Some(Socket::from(client_side).into()),
+            nowhere(),
+            config,
+        );
+
+        let unanswered = client.request(rmpv::Value::from("hello?")).await;
+        let error = unanswered.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<MailboxError>(),
+            Some(&MailboxError::TimedOut)
+        );
+
+        let (dropped, _) = tokio::join!(client.request(rmpv::Value::from("hello?")), async {
+            drop(server_side)
+        });
+        let error = dropped.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<MailboxError>(),
+            Some(&MailboxError::Closed)
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use tokio::{sync::oneshot, time};
+
+use super::ClientSink;
+
+use crate::{codec::Envelope, runtime::MailboxError};
+
+type Reply = oneshot::Sender<Result<rmpv::Value, MailboxError>>;
+type Waiting = HashMap<u64, (Instant, Reply)>;
+
+/// Requests sent over a connection that are still waiting on their reply, by envelope id.
+#[derive(Clone)]
+pub(super) struct Pending {
+    waiting: Arc<Mutex<Waiting>>,
+    timeout: Duration,
+}
+
+impl Pending {
+    pub(super) fn new(timeout: Duration) -> Self {
+        Self {
+            waiting: Arc::new(Mutex::new(HashMap::new())),
+            timeout,
+        }
+    }
+
+    /// Sends `body` as a request through `sink`, resolving with the body of its reply.
+    pub(super) async fn ask<Out>(
+        &self,
+        sink: &ClientSink<Out>,
+        body: rmpv::Value,
+    ) -> Result<rmpv::Value, MailboxError> {
+        // The server skips requests past their deadline, the asker gave up on them already
+        let request = Envelope::request(body, Some(self.timeout));
+        let id = request.id;
+        let deadline = request.deadline.unwrap_or_else(Instant::now);
+        let (reply, response) = oneshot::channel();
+        self.lock().insert(id, (deadline, reply));
+
+        if sink.send_value(request.to_value()).is_err() {
+            self.lock().remove(&id);
+
+            return Err(MailboxError::Closed);
+        }
+
+        match time::timeout(self.timeout, response).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(MailboxError::Closed),
+            Err(_) => {
+                self.lock().remove(&id);
+
+                Err(MailboxError::TimedOut)
+            }
+        }
+    }
+
+    /// Hands `reply` to the request it answers, if that's still waiting.
+    pub(super) fn resolve(&self, reply: Envelope) {
+        let id = match reply.reply_to {
+            Some(id) => id,
+            None => return,
+        };
+
+        if let Some((_, waiting)) = self.lock().remove(&id) {
+            waiting.send(reply.body.ok_or(MailboxError::Canceled)).ok();
+        }
+    }
+
+    /// What's left of an outbox `value` to write after reconnecting.
+    ///
+    /// Requests nobody waits on anymore are dropped, the others only get the
+    /// time they have left.
+    pub(super) fn replayable(&self, value: rmpv::Value) -> Option<rmpv::Value> {
+        let request = match Envelope::from_value(&value) {
+            Some(request) if request.reply_to.is_none() => request,
+            _ => return Some(value),
+        };
+
+        let deadline = self
+            .lock()
+            .get(&request.id)
+            .map(|(deadline, _)| *deadline)
+            .filter(|deadline| *deadline > Instant::now())?;
+
+        Some(
+            Envelope {
+                deadline: Some(deadline),
+                ..request
+            }
+            .to_value(),
+        )
+    }
+
+    /// Fails every request in flight, their replies can't arrive on another connection.
+    pub(super) fn disconnected(&self) {
+        for (_, (_, waiting)) in self.lock().drain() {
+            waiting.send(Err(MailboxError::Closed)).ok();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Waiting> {
+        self.waiting.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
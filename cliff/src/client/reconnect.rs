@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+use crate::runtime::Message;
+
+/// Where a client's connection to its server stands.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionState {
+    Connected,
+    /// The connection was lost. Reconnecting clients follow up with `Reconnecting`.
+    Disconnected,
+    /// The next connection attempt is scheduled `delay` from now.
+    Reconnecting {
+        attempt: u32,
+        delay: Duration,
+    },
+}
+impl Message for ConnectionState {}
+
+/// How a client gets back in touch with a server that went away.
+///
+/// Delays double with every failed attempt, up to `max_backoff`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reconnect {
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    outbox: Option<usize>,
+}
+
+impl Reconnect {
+    pub fn new() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+            outbox: None,
+        }
+    }
+
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+
+        self
+    }
+
+    /// Keeps up to `capacity` messages sent while disconnected, replaying them once reconnected.
+    ///
+    /// The oldest ones are dropped when it overflows. Without an outbox, sending fails
+    /// while disconnected.
+    pub fn with_outbox(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0, "An outbox needs room for one message");
+        self.outbox = Some(capacity);
+
+        self
+    }
+
+    pub(super) fn outbox(&self) -> Option<usize> {
+        self.outbox
+    }
+
+    pub(super) fn delay(&self, attempt: u32) -> Duration {
+        // Past 2^16 the cap has long been reached anyway
+        let factor = 1u32 << attempt.min(16);
+
+        self.initial_backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |delay| delay.min(self.max_backoff))
+    }
+}
+
+impl Default for Reconnect {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backs_off_exponentially_up_to_the_cap() {
+        let policy =
+            Reconnect::new().with_backoff(Duration::from_millis(100), Duration::from_secs(1));
+
+        assert_eq!(policy.delay(0), Duration::from_millis(100));
+        assert_eq!(policy.delay(1), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(800));
+        assert_eq!(policy.delay(4), Duration::from_secs(1));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,94 @@
+use std::sync::Arc;
+
+use failure::Error;
+
+use serde::de::DeserializeOwned;
+
+use super::{create_client_with, pending::Pending, ClientConfig, ClientSink};
+
+use crate::{
+    codec::{from_value, Registered, Registry},
+    runtime::{Handled, MailboxError, Request, SendError},
+};
+
+/// Reaches an actor `T` living in another process, over a client connection.
+///
+/// Works like an `Address`, messages and requests being encoded through `T`'s
+/// `Registry`. Replies are matched to their request by envelope id.
+pub struct RemoteAddress<T> {
+    sink: ClientSink<rmpv::Value>,
+    pending: Pending,
+    registry: Arc<Registry<T>>,
+}
+
+impl<T> Clone for RemoteAddress<T> {
+    fn clone(&self) -> Self {
+        Self {
+            sink: self.sink.clone(),
+            pending: self.pending.clone(),
+            registry: self.registry.clone(),
+        }
+    }
+}
+
+impl<T: Registered + 'static> RemoteAddress<T> {
+    pub(super) fn new(sink: ClientSink<rmpv::Value>, pending: Pending) -> Self {
+        Self {
+            sink,
+            pending,
+            registry: Arc::new(T::registry()),
+        }
+    }
+
+    /// Connects as `config` describes, the connection closing once every clone is dropped.
+    pub async fn connect(config: ClientConfig) -> Result<Self, Error> {
+        let client = create_client_with::<rmpv::Value, rmpv::Value>(config).await?;
+
+        Ok(client.address())
+    }
+
+    /// Writes `message` to the connection, handing it back if that's closed.
+    ///
+    /// Messages that aren't registered in `T`'s `Registry`, or don't serialize,
+    /// come back as `SendError::Unencodable`.
+    pub async fn send<M: Handled<T> + 'static>(&self, message: M) -> Result<(), SendError<M>> {
+        self.try_send(message)
+    }
+
+    /// Same as `send`, which never has to wait for room.
+    pub fn try_send<M: Handled<T> + 'static>(&self, message: M) -> Result<(), SendError<M>> {
+        let value = match self.registry.encode(&message) {
+            Ok(value) => value,
+            Err(_) => return Err(SendError::Unencodable(message)),
+        };
+
+        self.sink
+            .send(value)
+            .map_err(|_| SendError::Closed(message))
+    }
+
+    /// Sends a request registered with `register_request`, resolving with its reply.
+    ///
+    /// Fails with `MailboxError::TimedOut` once the client's request timeout
+    /// passes, and with `MailboxError::Closed` if the connection drops first.
+    /// Requests that can't be encoded fail with `MailboxError::Unencodable`.
+    pub async fn ask<M>(&self, message: M) -> Result<M::Result, MailboxError>
+    where
+        M: Request + 'static,
+        M::Result: DeserializeOwned,
+    {
+        let body = self
+            .registry
+            .encode_request(&message)
+            .map_err(|_| MailboxError::Unencodable)?;
+        let result = self.pending.ask(&self.sink, body).await?;
+
+        // A reply that doesn't decode is as good as none
+        from_value(&result).map_err(|_| MailboxError::Canceled)
+    }
+
+    /// Whether the connection to `T` is up.
+    pub fn is_connected(&self) -> bool {
+        self.sink.is_connected()
+    }
+}
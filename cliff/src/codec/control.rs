@@ -0,0 +1,141 @@
+use std::time::Duration;
+
+use futures::future;
+
+use tokio::time::{self, Instant, Interval};
+
+// Ext type codes reserved for control frames, which never reach an actor
+const PING: i8 = 1;
+const PONG: i8 = 2;
+
+/// Frames the protocol exchanges for itself, next to actor messages.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Control {
+    Ping,
+    Pong,
+}
+
+impl Control {
+    pub(crate) fn from_value(value: &rmpv::Value) -> Option<Self> {
+        match value {
+            rmpv::Value::Ext(PING, _) => Some(Control::Ping),
+            rmpv::Value::Ext(PONG, _) => Some(Control::Pong),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn to_value(self) -> rmpv::Value {
+        match self {
+            Control::Ping => rmpv::Value::Ext(PING, vec![]),
+            Control::Pong => rmpv::Value::Ext(PONG, vec![]),
+        }
+    }
+}
+
+/// How often a peer is pinged, and how long it may stay silent before it's dropped.
+///
+/// Any frame counts as a sign of life, not only pongs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Heartbeat {
+    interval: Duration,
+    timeout: Duration,
+}
+
+impl Heartbeat {
+    pub fn new(interval: Duration, timeout: Duration) -> Self {
+        assert!(
+            timeout > interval,
+            "A heartbeat timeout must leave room for a ping to be answered"
+        );
+
+        Self { interval, timeout }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(5), Duration::from_secs(15))
+    }
+}
+
+pub(crate) enum Beat {
+    /// Time to ping the peer.
+    Ping,
+    /// The peer missed its heartbeats.
+    Dead,
+}
+
+/// Keeps track of when a connection last heard from its peer.
+pub(crate) struct Liveness {
+    heartbeat: Option<(Heartbeat, Interval)>,
+    last_seen: Instant,
+}
+
+impl Liveness {
+    pub(crate) fn new(heartbeat: Option<Heartbeat>) -> Self {
+        let now = Instant::now();
+
+        Self {
+            heartbeat: heartbeat.map(|heartbeat| {
+                let ticks = time::interval_at(now + heartbeat.interval, heartbeat.interval);
+
+                (heartbeat, ticks)
+            }),
+            last_seen: now,
+        }
+    }
+
+    pub(crate) fn saw_frame(&mut self) {
+        self.last_seen = Instant::now();
+    }
+
+    /// Never resolves without a `Heartbeat`.
+    pub(crate) async fn tick(&mut self) -> Beat {
+        let (heartbeat, ticks) = match &mut self.heartbeat {
+            Some(heartbeat) => heartbeat,
+            None => return future::pending().await,
+        };
+
+        let now = ticks.tick().await;
+        if now.duration_since(self.last_seen) > heartbeat.timeout {
+            Beat::Dead
+        } else {
+            Beat::Ping
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_frames_round_trip() {
+        for control in &[Control::Ping, Control::Pong] {
+            assert_eq!(Control::from_value(&control.to_value()), Some(*control));
+        }
+
+        assert_eq!(Control::from_value(&rmpv::Value::from("Ping")), None);
+    }
+
+    #[tokio::test]
+    async fn silent_peers_are_declared_dead() {
+        let heartbeat = Heartbeat::new(Duration::from_millis(10), Duration::from_millis(25));
+        let mut liveness = Liveness::new(Some(heartbeat));
+
+        let mut pings = 0;
+        while let Beat::Ping = liveness.tick().await {
+            pings += 1;
+        }
+
+        assert!(pings >= 2);
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use rmpv::{decode::value::read_value, encode::write_value};
+
+// Ext type code for enveloped frames, next to the control and handshake ones
+const ENVELOPE: i8 = 8;
+
+static MESSAGE_IDS: AtomicU64 = AtomicU64::new(0);
+
+/// Wraps a frame with what it takes to match replies to the requests they answer.
+///
+/// Travels as `[id, reply_to, timeout, body]`, the timeout being the milliseconds
+/// left until the deadline when the frame was written. Receivers count it from
+/// arrival, so the two ends' clocks needn't agree. A reply without a body means
+/// the request was dropped.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Envelope {
+    pub(crate) id: u64,
+    pub(crate) reply_to: Option<u64>,
+    /// When the sender stops waiting on a reply, by this process' clock.
+    pub(crate) deadline: Option<Instant>,
+    pub(crate) body: Option<rmpv::Value>,
+}
+
+impl Envelope {
+    pub(crate) fn request(body: rmpv::Value, timeout: Option<Duration>) -> Self {
+        Self {
+            id: MESSAGE_IDS.fetch_add(1, Ordering::Relaxed),
+            reply_to: None,
+            deadline: timeout.map(|timeout| Instant::now() + timeout),
+            body: Some(body),
+        }
+    }
+
+    /// Answers the request `id` with `body`, or tells it was dropped without one.
+    pub(crate) fn reply(id: u64, body: Option<rmpv::Value>) -> Self {
+        Self {
+            id: MESSAGE_IDS.fetch_add(1, Ordering::Relaxed),
+            reply_to: Some(id),
+            deadline: None,
+            body,
+        }
+    }
+
+    pub(crate) fn is_expired(&self) -> bool {
+        match self.deadline {
+            Some(deadline) => Instant::now() > deadline,
+            None => false,
+        }
+    }
+
+    pub(crate) fn from_value(value: &rmpv::Value) -> Option<Self> {
+        let payload = match value {
+            rmpv::Value::Ext(ENVELOPE, payload) => payload,
+            _ => return None,
+        };
+
+        let mut fields = match read_value(&mut payload.as_slice()).ok()? {
+            rmpv::Value::Array(fields) if fields.len() == 3 || fields.len() == 4 => fields,
+            _ => return None,
+        };
+        let body = if fields.len() == 4 {
+            fields.pop()
+        } else {
+            None
+        };
+        let deadline =
+            optional(fields.pop()?)?.map(|millis| Instant::now() + Duration::from_millis(millis));
+        let reply_to = optional(fields.pop()?)?;
+
+        Some(Self {
+            id: fields.pop()?.as_u64()?,
+            reply_to,
+            deadline,
+            body,
+        })
+    }
+
+    pub(crate) fn to_value(&self) -> rmpv::Value {
+        let timeout = self.deadline.map(|deadline| {
+            let left = deadline.saturating_duration_since(Instant::now());
+
+            left.as_millis() as u64
+        });
+
+        // Leaving the body out keeps a dropped request apart from a `nil` reply
+        let mut fields = vec![
+            rmpv::Value::from(self.id),
+            self.reply_to.map_or(rmpv::Value::Nil, rmpv::Value::from),
+            timeout.map_or(rmpv::Value::Nil, rmpv::Value::from),
+        ];
+        fields.extend(self.body.clone());
+
+        let mut payload = vec![];
+        write_value(&mut payload, &rmpv::Value::Array(fields))
+            .expect("Invariant Violation: Writing to a Vec can't fail");
+
+        rmpv::Value::Ext(ENVELOPE, payload)
+    }
+}
+
+/// `Some(None)` for `nil`, `None` for anything but an unsigned integer.
+fn optional(value: rmpv::Value) -> Option<Option<u64>> {
+    match value {
+        rmpv::Value::Nil => Some(None),
+        value => value.as_u64().map(Some),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn envelopes_round_trip() {
+        let request = Envelope::request(rmpv::Value::from("Count"), None);
+        let envelopes = [
+            request.clone(),
+            Envelope::reply(request.id, Some(rmpv::Value::from(3))),
+            Envelope::reply(request.id, Some(rmpv::Value::Nil)),
+            Envelope::reply(request.id, None),
+        ];
+
+        for envelope in &envelopes {
+            let decoded = Envelope::from_value(&envelope.to_value());
+            assert_eq!(decoded.as_ref(), Some(envelope));
+        }
+
+        assert!(!request.is_expired());
+        assert_ne!(envelopes[1].id, request.id);
+        assert_eq!(Envelope::from_value(&rmpv::Value::from(7)), None);
+    }
+
+    #[test]
+    fn deadlines_travel_as_time_left() {
+        let timeout = Duration::from_secs(30);
+        let request = Envelope::request(rmpv::Value::from("Count"), Some(timeout));
+
+        let fields = match request.to_value() {
+            rmpv::Value::Ext(ENVELOPE, payload) => read_value(&mut payload.as_slice()).unwrap(),
+            other => panic!("Expected an envelope, got {}", other),
+        };
+        let left = fields.as_array().unwrap()[2].as_u64().unwrap();
+        assert!(left <= 30_000 && left > 29_000);
+
+        let decoded = Envelope::from_value(&request.to_value()).unwrap();
+        assert!(decoded.deadline.unwrap() <= Instant::now() + timeout);
+        assert!(!decoded.is_expired());
+
+        let late = Envelope::request(rmpv::Value::from("Count"), Some(Duration::from_secs(0)));
+        let late = Envelope::from_value(&late.to_value()).unwrap();
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(late.is_expired());
+    }
+}
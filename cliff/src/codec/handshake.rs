@@ -0,0 +1,451 @@
+use std::{error, fmt, time::Duration};
+
+use failure::{format_err, Error};
+
+use futures::{sink::SinkExt, stream::StreamExt, Sink, Stream};
+
+use hmac::{Hmac, Mac};
+
+use rmpv::{decode::value::read_value, encode::write_value};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use sha2::Sha256;
+
+use tokio::time;
+
+use super::negotiation::{Capabilities, Negotiated, Versions};
+
+// Ext type codes reserved for handshake frames, next to the control ones
+const CHALLENGE: i8 = 3;
+const HELLO: i8 = 4;
+const WELCOME: i8 = 5;
+const VERSION_MISMATCH: i8 = 6;
+const UNAUTHORIZED: i8 = 7;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A token shared by a server and the clients it lets in.
+///
+/// It never goes over the wire: clients prove they know it by signing a one-time
+/// challenge from the server.
+#[derive(Clone)]
+pub struct Secret(Vec<u8>);
+
+impl Secret {
+    pub fn new(token: impl AsRef<[u8]>) -> Self {
+        Self(token.as_ref().to_vec())
+    }
+
+    fn mac(&self, nonce: &[u8]) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC takes keys of any size");
+        mac.update(nonce);
+
+        mac
+    }
+
+    fn sign(&self, nonce: &[u8]) -> Vec<u8> {
+        self.mac(nonce).finalize().into_bytes().to_vec()
+    }
+
+    fn verify(&self, nonce: &[u8], proof: &[u8]) -> bool {
+        self.mac(nonce).verify_slice(proof).is_ok()
+    }
+}
+
+// Keeps the token out of logs
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret(..)")
+    }
+}
+
+/// Why a server turned a client away during the handshake.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rejection {
+    /// The peers don't have a protocol version in common.
+    VersionMismatch {
+        server: Versions,
+        client: Versions,
+    },
+    Unauthorized,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::VersionMismatch { server, client } => write!(
+                f,
+                "Server speaks protocol versions {}, client speaks {}",
+                server, client
+            ),
+            Rejection::Unauthorized => write!(f, "Server didn't accept the client's credentials"),
+        }
+    }
+}
+
+impl error::Error for Rejection {}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Challenge {
+    nonce: Vec<u8>,
+}
+
+/// How a client introduces itself.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Hello {
+    versions: Versions,
+    name: String,
+    proof: Option<Vec<u8>>,
+    capabilities: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Welcome {
+    version: u32,
+    capabilities: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Mismatch {
+    server: Versions,
+    client: Versions,
+}
+
+/// A client the server let in.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Admission {
+    pub(crate) name: String,
+    pub(crate) negotiated: Negotiated,
+}
+
+fn pack<S: Serialize>(code: i8, frame: &S) -> rmpv::Value {
+    let mut payload = vec![];
+    let value = rmpv::ext::to_value(frame).expect("Handshake frames are always serializable");
+    write_value(&mut payload, &value).expect("Writing to a Vec can't fail");
+
+    rmpv::Value::Ext(code, payload)
+}
+
+fn unpack<D: DeserializeOwned>(payload: &[u8]) -> Option<D> {
+    let value = read_value(&mut &payload[..]).ok()?;
+
+    rmpv::ext::from_value(value).ok()
+}
+
+async fn next_frame<R>(reader: &mut R) -> Result<rmpv::Value, Error>
+where
+    R: Stream<Item = Result<rmpv::Value, Error>> + Unpin,
+{
+    match time::timeout(HANDSHAKE_TIMEOUT, reader.next()).await {
+        Ok(Some(frame)) => frame,
+        Ok(None) => Err(format_err!("Connection closed during the handshake")),
+        Err(_) => Err(format_err!("Handshake timed out")),
+    }
+}
+
+/// What a server asks of connecting clients.
+#[derive(Debug, Clone)]
+pub(crate) struct Gate {
+    secret: Option<Secret>,
+    /// Whether clients get in without a secret when none is set.
+    open: bool,
+    versions: Versions,
+    capabilities: Capabilities,
+}
+
+impl Gate {
+    pub(crate) fn new(secret: Option<Secret>, open: bool) -> Self {
+        Self {
+            secret,
+            open,
+            versions: Versions::supported(),
+            capabilities: Capabilities::all(),
+        }
+    }
+
+    /// Runs the server side of the handshake, settling on what both peers support.
+    pub(crate) async fn admit<R, W>(
+        &self,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Result<Admission, Error>
+    where
+        R: Stream<Item = Result<rmpv::Value, Error>> + Unpin,
+        W: Sink<rmpv::Value, Error = Error> + Unpin,
+    {
+        let challenge = Challenge {
+            nonce: rand::random::<[u8; 32]>().to_vec(),
+        };
+        writer.send(pack(CHALLENGE, &challenge)).await?;
+
+        let hello = match next_frame(reader).await? {
+            rmpv::Value::Ext(HELLO, payload) => unpack::<Hello>(&payload),
+            _ => None,
+        };
+        let verdict = match hello {
+            Some(hello) => self.check(&challenge, hello),
+            None => Err(Rejection::Unauthorized),
+        };
+
+        match verdict {
+            Ok(admission) => {
+                let welcome = Welcome {
+                    version: admission.negotiated.version,
+                    capabilities: admission.negotiated.capabilities.to_names(),
+                };
+                writer.send(pack(WELCOME, &welcome)).await?;
+
+                Ok(admission)
+            }
+            Err(rejection) => {
+                writer.send(rejection.to_value()).await.ok();
+                Err(Error::from(rejection))
+            }
+        }
+    }
+
+    fn check(&self, challenge: &Challenge, hello: Hello) -> Result<Admission, Rejection> {
+        let mismatch = Rejection::VersionMismatch {
+            server: self.versions,
+            client: hello.versions,
+        };
+        let version = self.versions.common(&hello.versions).ok_or(mismatch)?;
+
+        let authorized = match (&self.secret, &hello.proof) {
+            (Some(secret), Some(proof)) => secret.verify(&challenge.nonce, proof),
+            (Some(_), None) => false,
+            (None, _) => self.open,
+        };
+        if !authorized {
+            return Err(Rejection::Unauthorized);
+        }
+
+        let capabilities = Capabilities::from_names(&hello.capabilities);
+        Ok(Admission {
+            name: hello.name,
+            negotiated: Negotiated {
+                version,
+                capabilities: self.capabilities.common(&capabilities),
+            },
+        })
+    }
+}
+
+impl Rejection {
+    fn to_value(&self) -> rmpv::Value {
+        match self {
+            Rejection::VersionMismatch { server, client } => pack(
+                VERSION_MISMATCH,
+                &Mismatch {
+                    server: *server,
+                    client: *client,
+                },
+            ),
+            Rejection::Unauthorized => rmpv::Value::Ext(UNAUTHORIZED, vec![]),
+        }
+    }
+}
+
+/// Who a client says it is, and the secret it proves it's allowed in with.
+#[derive(Debug, Clone)]
+pub(crate) struct Identity {
+    name: String,
+    secret: Option<Secret>,
+    versions: Versions,
+    capabilities: Capabilities,
+}
+
+impl Identity {
+    pub(crate) fn new(name: String, secret: Option<Secret>) -> Self {
+        Self {
+            name,
+            secret,
+            versions: Versions::supported(),
+            capabilities: Capabilities::all(),
+        }
+    }
+
+    /// Runs the client side of the handshake, failing with a `Rejection` when turned away.
+    pub(crate) async fn introduce<R, W>(
+        &self,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Result<Negotiated, Error>
+    where
+        R: Stream<Item = Result<rmpv::Value, Error>> + Unpin,
+        W: Sink<rmpv::Value, Error = Error> + Unpin,
+    {
+        let challenge = match next_frame(reader).await? {
+            rmpv::Value::Ext(CHALLENGE, payload) => unpack::<Challenge>(&payload),
+            _ => None,
+        }
+        .ok_or_else(|| format_err!("Expected a handshake challenge"))?;
+
+        let hello = Hello {
+            versions: self.versions,
+            name: self.name.clone(),
+            proof: self
+                .secret
+                .as_ref()
+                .map(|secret| secret.sign(&challenge.nonce)),
+            capabilities: self.capabilities.to_names(),
+        };
+        writer.send(pack(HELLO, &hello)).await?;
+
+        match next_frame(reader).await? {
+            rmpv::Value::Ext(WELCOME, payload) => {
+                let welcome = unpack::<Welcome>(&payload)
+                    .ok_or_else(|| format_err!("Malformed welcome frame"))?;
+                let capabilities = Capabilities::from_names(&welcome.capabilities);
+
+                Ok(Negotiated {
+                    version: welcome.version,
+                    // Never more than what was offered, whatever the server says
+                    capabilities: self.capabilities.common(&capabilities),
+                })
+            }
+            rmpv::Value::Ext(VERSION_MISMATCH, payload) => {
+                let mismatch = unpack::<Mismatch>(&payload)
+                    .ok_or_else(|| format_err!("Malformed version mismatch frame"))?;
+
+                Err(Error::from(Rejection::VersionMismatch {
+                    server: mismatch.server,
+                    client: mismatch.client,
+                }))
+            }
+            rmpv::Value::Ext(UNAUTHORIZED, _) => Err(Error::from(Rejection::Unauthorized)),
+            _ => Err(format_err!("Expected the handshake's outcome")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::channel::mpsc;
+
+    use crate::codec::negotiation::Capability;
+
+    type Reader = mpsc::UnboundedReceiver<Result<rmpv::Value, Error>>;
+    type Writer = futures::sink::SinkErrInto<
+        mpsc::UnboundedSender<Result<rmpv::Value, Error>>,
+        Result<rmpv::Value, Error>,
+        Error,
+    >;
+
+    fn pipe() -> (Reader, impl Sink<rmpv::Value, Error = Error> + Unpin) {
+        let (sender, receiver) = mpsc::unbounded();
+        let sender: Writer = sender.sink_err_into();
+
+        (
+            receiver,
+            sender.with(|value| futures::future::ok(Ok(value))),
+        )
+    }
+
+    async fn shake(
+        gate: Gate,
+        identity: Identity,
+    ) -> (Result<Admission, Error>, Result<Negotiated, Error>) {
+        let (mut server_reader, mut client_writer) = pipe();
+        let (mut client_reader, mut server_writer) = pipe();
+
+        futures::join!(
+            gate.admit(&mut server_reader, &mut server_writer),
+            identity.introduce(&mut client_reader, &mut client_writer)
+        )
+    }
+
+    fn identity(secret: Option<&str>) -> Identity {
+        Identity::new("cat".to_string(), secret.map(Secret::new))
+    }
+
+    #[tokio::test]
+    async fn admits_clients_that_know_the_secret() {
+        let gate = Gate::new(Some(Secret::new("meow")), false);
+        let (admitted, introduced) = shake(gate, identity(Some("meow"))).await;
+
+        let admission = admitted.unwrap();
+        assert_eq!(admission.name, "cat");
+        assert_eq!(introduced.unwrap(), admission.negotiated);
+    }
+
+    #[tokio::test]
+    async fn rejects_wrong_or_missing_secrets() {
+        for secret in &[Some("woof"), None] {
+            let gate = Gate::new(Some(Secret::new("meow")), true);
+            let (admitted, introduced) = shake(gate, identity(*secret)).await;
+
+            assert!(admitted.is_err());
+            assert_eq!(
+                introduced.unwrap_err().downcast_ref::<Rejection>(),
+                Some(&Rejection::Unauthorized)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn only_open_gates_admit_anyone() {
+        let (admitted, _) = shake(Gate::new(None, true), identity(None)).await;
+        assert!(admitted.is_ok());
+
+        let (admitted, _) = shake(Gate::new(None, false), identity(None)).await;
+        assert!(admitted.is_err());
+    }
+
+    #[tokio::test]
+    async fn negotiates_across_versions() {
+        let matrix = [
+            (Versions::new(1, 1), Versions::new(1, 1), Some(1)),
+            (Versions::new(1, 2), Versions::new(1, 1), Some(1)),
+            (Versions::new(1, 1), Versions::new(1, 2), Some(1)),
+            (Versions::new(2, 3), Versions::new(1, 4), Some(3)),
+            (Versions::new(2, 2), Versions::new(1, 1), None),
+            (Versions::new(1, 1), Versions::new(2, 2), None),
+        ];
+
+        for &(server, client, expected) in &matrix {
+            let mut gate = Gate::new(None, true);
+            gate.versions = server;
+            let mut identity = identity(None);
+            identity.versions = client;
+
+            let (admitted, introduced) = shake(gate, identity).await;
+            match expected {
+                Some(version) => {
+                    assert_eq!(admitted.unwrap().negotiated.version, version);
+                    assert_eq!(introduced.unwrap().version, version);
+                }
+                None => {
+                    let mismatch = Rejection::VersionMismatch { server, client };
+                    assert!(admitted.is_err());
+                    assert_eq!(
+                        introduced.unwrap_err().downcast_ref::<Rejection>(),
+                        Some(&mismatch)
+                    );
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn agrees_on_common_capabilities() {
+        let mut identity = identity(None);
+        identity.capabilities = Capabilities::none();
+
+        let (admitted, introduced) = shake(Gate::new(None, true), identity).await;
+        assert_eq!(
+            admitted.unwrap().negotiated.capabilities,
+            Capabilities::none()
+        );
+        assert_eq!(introduced.unwrap().capabilities, Capabilities::none());
+
+        let (_, introduced) = shake(Gate::new(None, true), self::identity(None)).await;
+        assert!(introduced
+            .unwrap()
+            .capabilities
+            .contains(Capability::Heartbeat));
+    }
+}
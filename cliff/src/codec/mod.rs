@@ -1,3 +1,5 @@
+use std::io;
+
 use tokio_util::codec::{Decoder, Encoder};
 
 use bytes::{buf::*, BytesMut};
@@ -6,9 +8,70 @@ use failure::{Error, ResultExt};
 
 use rmpv::{self, decode::value::read_value, encode::write_value};
 
+mod control;
+mod envelope;
+mod handshake;
+mod negotiation;
+mod registry;
+mod typed;
+
+pub use control::Heartbeat;
+pub(crate) use control::{Beat, Control, Liveness};
+pub(crate) use envelope::Envelope;
+pub(crate) use handshake::{Gate, Identity};
+pub use handshake::{Rejection, Secret};
+pub use negotiation::{
+    Capabilities, Capability, Negotiated, Versions, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+pub use registry::{Registered, Registry};
+pub(crate) use typed::{from_value, to_value};
+
 pub trait Protocol {}
 
-pub struct MsgPackCodec {}
+/// Largest frame a `MsgPackCodec` decodes unless told otherwise.
+pub const DEFAULT_MAX_FRAME: usize = 8 * 1024 * 1024;
+
+/// Largest frame decoded before a peer is let in, handshake frames being small.
+pub(crate) const HANDSHAKE_MAX_FRAME: usize = 4 * 1024;
+
+/// Frames msgpack values, rejecting the ones over `max_frame` bytes.
+///
+/// Sizes come from the frame's own header, so they're checked before any
+/// room is made for the frame.
+#[derive(Debug, Clone, Copy)]
+pub struct MsgPackCodec {
+    max_frame: usize,
+}
+
+impl MsgPackCodec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_frame(mut self, max_frame: usize) -> Self {
+        self.max_frame = max_frame;
+
+        self
+    }
+
+    /// Oversized frames can't be skipped without reading them, so they fail like the socket.
+    fn too_large(&self, size: usize) -> Error {
+        let message = format!(
+            "Frame of {} bytes is over the {} byte limit",
+            size, self.max_frame
+        );
+
+        io::Error::new(io::ErrorKind::InvalidData, message).into()
+    }
+}
+
+impl Default for MsgPackCodec {
+    fn default() -> Self {
+        Self {
+            max_frame: DEFAULT_MAX_FRAME,
+        }
+    }
+}
 
 impl Encoder for MsgPackCodec {
     type Item = rmpv::Value;
@@ -31,6 +94,7 @@ impl Decoder for MsgPackCodec {
         }
 
         let size = match get_value_size(src) {
+            Some(size) if size > self.max_frame => return Err(self.too_large(size)),
             Some(size) if src.len() >= size => size,
             Some(size) => {
                 let needed = size - src.len() + 16;
@@ -38,6 +102,8 @@ impl Decoder for MsgPackCodec {
 
                 return Ok(None);
             }
+            // Whatever is buffered belongs to the first frame, as it isn't complete yet
+            None if src.len() > self.max_frame => return Err(self.too_large(src.len())),
             None => return Ok(None),
         };
         let to_parse = src.split_to(size);
@@ -160,3 +226,46 @@ fn get_value_size(buffer: &[u8]) -> Option<usize> {
         0xdf => size_compound(&buffer, get_be_size(&buffer, S32) * 2, 5),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_frames_within_the_limit() {
+        let mut codec = MsgPackCodec::new().with_max_frame(16);
+        let mut buffer = BytesMut::new();
+
+        codec
+            .encode(rmpv::Value::from("meow"), &mut buffer)
+            .unwrap();
+        codec.encode(rmpv::Value::from(7), &mut buffer).unwrap();
+
+        assert_eq!(
+            codec.decode(&mut buffer).unwrap(),
+            Some(rmpv::Value::from("meow"))
+        );
+        assert_eq!(
+            codec.decode(&mut buffer).unwrap(),
+            Some(rmpv::Value::from(7))
+        );
+        assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_oversized_frames_before_buffering_them() {
+        let mut codec = MsgPackCodec::new();
+
+        // A bin(32) header claiming 4 GiB of payload
+        let mut buffer = BytesMut::from(&[0xc6, 0xff, 0xff, 0xff, 0xff][..]);
+        let error = codec.decode(&mut buffer).unwrap_err();
+        assert!(error.downcast_ref::<io::Error>().is_some());
+        assert!(buffer.capacity() < 1024);
+
+        // An array(32) whose elements keep coming
+        let mut codec = MsgPackCodec::new().with_max_frame(HANDSHAKE_MAX_FRAME);
+        let mut buffer = BytesMut::from(&[0xdd, 0xff, 0xff, 0xff, 0xff][..]);
+        buffer.extend_from_slice(&[0xc0; HANDSHAKE_MAX_FRAME]);
+        assert!(codec.decode(&mut buffer).is_err());
+    }
+}
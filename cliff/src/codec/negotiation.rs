@@ -0,0 +1,167 @@
+use std::{collections::BTreeSet, fmt};
+
+use serde::{Deserialize, Serialize};
+
+/// The newest protocol version this build speaks.
+pub const PROTOCOL_VERSION: u32 = 1;
+/// The oldest protocol version this build still speaks.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// The range of protocol versions a peer speaks, both ends included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Versions {
+    pub min: u32,
+    pub max: u32,
+}
+
+impl Versions {
+    pub fn new(min: u32, max: u32) -> Self {
+        assert!(min <= max, "A version range can't end before it starts");
+
+        Self { min, max }
+    }
+
+    /// What this build speaks.
+    pub fn supported() -> Self {
+        Self::new(MIN_PROTOCOL_VERSION, PROTOCOL_VERSION)
+    }
+
+    /// The newest version both ranges include, if any.
+    pub(crate) fn common(&self, other: &Versions) -> Option<u32> {
+        let newest = self.max.min(other.max);
+
+        if newest >= self.min.max(other.min) {
+            Some(newest)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Versions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.min == self.max {
+            write!(f, "{}", self.max)
+        } else {
+            write!(f, "{} to {}", self.min, self.max)
+        }
+    }
+}
+
+/// Optional protocol features, only used when both peers support them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Capability {
+    /// Ping and pong control frames.
+    Heartbeat,
+}
+
+impl Capability {
+    const ALL: &'static [Capability] = &[Capability::Heartbeat];
+
+    // Names go over the wire, so peers can skip the ones they don't know
+    fn name(self) -> &'static str {
+        match self {
+            Capability::Heartbeat => "heartbeat",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|capability| capability.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Capabilities(BTreeSet<Capability>);
+
+impl Capabilities {
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// Everything this build supports.
+    pub fn all() -> Self {
+        Self(Capability::ALL.iter().copied().collect())
+    }
+
+    pub fn with(mut self, capability: Capability) -> Self {
+        self.0.insert(capability);
+
+        self
+    }
+
+    pub fn contains(&self, capability: Capability) -> bool {
+        self.0.contains(&capability)
+    }
+
+    pub(crate) fn common(&self, other: &Capabilities) -> Self {
+        Self(self.0.intersection(&other.0).copied().collect())
+    }
+
+    pub(crate) fn to_names(&self) -> Vec<String> {
+        self.0
+            .iter()
+            .map(|capability| capability.name().to_string())
+            .collect()
+    }
+
+    pub(crate) fn from_names(names: &[String]) -> Self {
+        Self(
+            names
+                .iter()
+                .filter_map(|name| Capability::from_name(name))
+                .collect(),
+        )
+    }
+}
+
+/// What two peers agreed on during the handshake.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Negotiated {
+    pub version: u32,
+    pub capabilities: Capabilities,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_newest_common_version() {
+        let matrix = [
+            ((1, 1), (1, 1), Some(1)),
+            ((1, 2), (1, 1), Some(1)),
+            ((1, 1), (1, 2), Some(1)),
+            ((1, 3), (2, 4), Some(3)),
+            ((2, 2), (1, 3), Some(2)),
+            ((2, 3), (1, 1), None),
+            ((1, 1), (2, 3), None),
+        ];
+
+        for &((server_min, server_max), (client_min, client_max), expected) in &matrix {
+            let server = Versions::new(server_min, server_max);
+            let client = Versions::new(client_min, client_max);
+
+            assert_eq!(
+                server.common(&client),
+                expected,
+                "{} and {}",
+                server,
+                client
+            );
+        }
+    }
+
+    #[test]
+    fn skips_unknown_capabilities() {
+        let names = vec!["heartbeat".to_string(), "telepathy".to_string()];
+
+        assert_eq!(Capabilities::from_names(&names), Capabilities::all());
+        assert_eq!(
+            Capabilities::all().common(&Capabilities::none()),
+            Capabilities::none()
+        );
+    }
+}
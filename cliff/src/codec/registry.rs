@@ -0,0 +1,255 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+};
+
+use failure::{format_err, Error, ResultExt};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use tokio::sync::{mpsc::UnboundedSender, oneshot};
+
+use super::{from_value, to_value, Envelope, Heartbeat};
+
+use crate::runtime::{Ask, Handled, Request};
+
+type Decode<T> = fn(rmpv::Value) -> Result<Box<dyn Handled<T>>, Error>;
+type DecodeRequest<T> = fn(rmpv::Value, Replies) -> Result<Box<dyn Handled<T>>, Error>;
+type Encode = fn(&dyn Any) -> Result<rmpv::Value, Error>;
+
+/// Implemented by actors that can receive messages over a connection.
+///
+/// The returned registry lists every message type the actor accepts on the wire.
+pub trait Registered: Sized {
+    fn registry() -> Registry<Self>;
+
+    /// How connections to this actor are kept in check. No heartbeats unless overridden.
+    fn heartbeat() -> Option<Heartbeat> {
+        None
+    }
+}
+
+/// Where the reply to a request read off a connection is written.
+#[derive(Debug, Clone)]
+struct Replies {
+    id: u64,
+    sender: UnboundedSender<rmpv::Value>,
+}
+
+/// Maps wire type tags to the message types an actor `T` handles.
+///
+/// Every message travels as a two element array holding the message's type
+/// tag and its serialized payload. Requests come wrapped in an `Envelope`
+/// their reply is matched to.
+pub struct Registry<T> {
+    decoders: HashMap<String, Decode<T>>,
+    requests: HashMap<String, DecodeRequest<T>>,
+    encoders: HashMap<TypeId, (String, Encode)>,
+}
+
+impl<T> Default for Registry<T> {
+    fn default() -> Self {
+        Self {
+            decoders: HashMap::new(),
+            requests: HashMap::new(),
+            encoders: HashMap::new(),
+        }
+    }
+}
+
+impl<T: 'static> Registry<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<M>(&mut self, tag: &str) -> &mut Self
+    where
+        M: Handled<T> + Serialize + DeserializeOwned + 'static,
+    {
+        self.decoders
+            .insert(tag.to_string(), decode_message::<T, M>);
+        self.encoders
+            .insert(TypeId::of::<M>(), (tag.to_string(), encode_message::<M>));
+
+        self
+    }
+
+    /// Registers a request `T` answers, for `ask`s coming in over a connection.
+    pub fn register_request<M>(&mut self, tag: &str) -> &mut Self
+    where
+        M: Request + Serialize + DeserializeOwned + 'static,
+        M::Result: Serialize,
+        Ask<M>: Handled<T>,
+    {
+        self.requests
+            .insert(tag.to_string(), decode_request::<T, M>);
+        self.encoders
+            .insert(TypeId::of::<M>(), (tag.to_string(), encode_message::<M>));
+
+        self
+    }
+
+    pub fn decode(&self, value: rmpv::Value) -> Result<Box<dyn Handled<T>>, Error> {
+        let (tag, payload) = open_tagged(value)?;
+        let decode = self
+            .decoders
+            .get(&tag)
+            .ok_or_else(|| format_err!("Unknown message type: {}", tag))?;
+
+        decode(payload)
+    }
+
+    /// Decodes the request `envelope` holds, its reply being written to `replies`.
+    pub(crate) fn decode_request(
+        &self,
+        envelope: Envelope,
+        replies: &UnboundedSender<rmpv::Value>,
+    ) -> Result<Box<dyn Handled<T>>, Error> {
+        let body = envelope
+            .body
+            .ok_or_else(|| format_err!("Request envelope has no body"))?;
+        let (tag, payload) = open_tagged(body)?;
+        let decode = self
+            .requests
+            .get(&tag)
+            .ok_or_else(|| format_err!("Unknown request type: {}", tag))?;
+
+        decode(
+            payload,
+            Replies {
+                id: envelope.id,
+                sender: replies.clone(),
+            },
+        )
+    }
+
+    pub fn encode(&self, message: &dyn Handled<T>) -> Result<rmpv::Value, Error> {
+        let (tag, payload) = self.encode_any(message.as_any())?;
+
+        Ok(rmpv::Value::Array(vec![tag, payload]))
+    }
+
+    /// Encodes a request registered with `register_request`, to be put in an `Envelope`.
+    pub(crate) fn encode_request<M: Request + 'static>(
+        &self,
+        message: &M,
+    ) -> Result<rmpv::Value, Error> {
+        let (tag, payload) = self.encode_any(message)?;
+
+        Ok(rmpv::Value::Array(vec![tag, payload]))
+    }
+
+    fn encode_any(&self, message: &dyn Any) -> Result<(rmpv::Value, rmpv::Value), Error> {
+        let (tag, encode) = self
+            .encoders
+            .get(&message.type_id())
+            .ok_or_else(|| format_err!("Message type isn't registered"))?;
+
+        Ok((rmpv::Value::from(tag.as_str()), encode(message)?))
+    }
+}
+
+fn open_tagged(value: rmpv::Value) -> Result<(String, rmpv::Value), Error> {
+    let mut fields = match value {
+        rmpv::Value::Array(fields) if fields.len() == 2 => fields,
+        _ => return Err(format_err!("Malformed message envelope")),
+    };
+
+    let payload = fields.pop().unwrap();
+    match fields.pop().unwrap() {
+        rmpv::Value::String(tag) => match tag.into_str() {
+            Some(tag) => Ok((tag, payload)),
+            None => Err(format_err!("Message type tag isn't valid UTF-8")),
+        },
+        _ => Err(format_err!("Message type tag must be a string")),
+    }
+}
+
+fn decode_message<T, M>(payload: rmpv::Value) -> Result<Box<dyn Handled<T>>, Error>
+where
+    M: Handled<T> + DeserializeOwned + 'static,
+{
+    let message: M = from_value(&payload).context("Couldn't deserialize message payload")?;
+
+    Ok(Box::new(message))
+}
+
+fn decode_request<T, M>(
+    payload: rmpv::Value,
+    replies: Replies,
+) -> Result<Box<dyn Handled<T>>, Error>
+where
+    M: Request + DeserializeOwned + 'static,
+    M::Result: Serialize,
+    Ask<M>: Handled<T>,
+{
+    let message: M = from_value(&payload).context("Couldn't deserialize request payload")?;
+    let (reply, response) = oneshot::channel();
+
+    tokio::spawn(async move {
+        // Dropped requests are answered too, so the asker isn't left waiting
+        let result = response
+            .await
+            .ok()
+            .and_then(|result| to_value(&result).ok());
+        let reply = Envelope::reply(replies.id, result);
+
+        replies.sender.send(reply.to_value()).ok();
+    });
+
+    Ok(Box::new(Ask::new(message, reply)))
+}
+
+fn encode_message<M: Serialize + 'static>(message: &dyn Any) -> Result<rmpv::Value, Error> {
+    let message = message
+        .downcast_ref::<M>()
+        .ok_or_else(|| format_err!("Invariant Violation: Registered encoder got wrong type"))?;
+
+    Ok(to_value(message).context("Couldn't serialize message payload")?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use crate::testing::{Add, Count, Counter};
+
+    #[test]
+    fn round_trips_registered_messages() {
+        let registry = Counter::registry();
+
+        let value = registry.encode(&Add(3)).unwrap();
+        assert_eq!(value.as_array().unwrap()[0].as_str(), Some("Add"));
+
+        let decoded = registry.decode(value).unwrap();
+        assert_eq!(decoded.as_any().downcast_ref::<Add>(), Some(&Add(3)));
+    }
+
+    #[test]
+    fn rejects_unknown_tags() {
+        let registry = Counter::registry();
+        let value = rmpv::Value::Array(vec![rmpv::Value::from("Remove"), rmpv::Value::from(3)]);
+
+        assert!(registry.decode(value).is_err());
+    }
+
+    #[tokio::test]
+    async fn answers_requests_with_their_id() {
+        let registry = Counter::registry();
+        let (replies, mut written) = unbounded_channel();
+        let mut counter = Counter::new(4);
+
+        let request = Envelope::request(registry.encode_request(&Count).unwrap(), None);
+        let id = request.id;
+        assert!(registry.decode(request.body.clone().unwrap()).is_err());
+
+        let mut request = registry.decode_request(request, &replies).unwrap();
+        request.be_handled(&mut counter).await;
+
+        let reply = Envelope::from_value(&written.recv().await.unwrap()).unwrap();
+        assert_eq!(reply.reply_to, Some(id));
+        assert_eq!(reply.body, Some(rmpv::Value::from(4)));
+    }
+}
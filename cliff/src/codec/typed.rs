@@ -0,0 +1,88 @@
+use failure::{Error, ResultExt};
+
+use rmpv::{decode::value::read_value, encode::write_value};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Serializes `message` through rmp-serde, structs becoming maps keyed by field name.
+pub(crate) fn to_value<T: Serialize + ?Sized>(message: &T) -> Result<rmpv::Value, Error> {
+    let bytes = rmp_serde::to_vec_named(message).context("Couldn't serialize message")?;
+
+    Ok(read_value(&mut bytes.as_slice())?)
+}
+
+/// Deserializes a `T` from `value` through rmp-serde.
+///
+/// Structs are read from maps keyed by field name as well as from arrays.
+pub(crate) fn from_value<T: DeserializeOwned>(value: &rmpv::Value) -> Result<T, Error> {
+    let mut bytes = vec![];
+    write_value(&mut bytes, value)?;
+
+    Ok(rmp_serde::from_read_ref(&bytes).context("Couldn't deserialize message")?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Guess {
+        client_id: u16,
+        guess: u8,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Score(u32);
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Event {
+        Started,
+        Guessed(Guess),
+        Won { client_id: u16 },
+    }
+
+    fn round_trip<T>(message: T)
+    where
+        T: Serialize + DeserializeOwned + PartialEq + std::fmt::Debug,
+    {
+        let value = to_value(&message).unwrap();
+
+        assert_eq!(from_value::<T>(&value).unwrap(), message);
+    }
+
+    #[test]
+    fn structs_travel_as_named_maps() {
+        let guess = Guess {
+            client_id: 3,
+            guess: 7,
+        };
+        let value = to_value(&guess).unwrap();
+
+        let fields = value.as_map().unwrap();
+        assert_eq!(fields[0].0.as_str(), Some("client_id"));
+        assert_eq!(fields[1].0.as_str(), Some("guess"));
+
+        // Peers that send structs as arrays are still understood
+        let compact = rmpv::Value::Array(vec![rmpv::Value::from(3), rmpv::Value::from(7)]);
+        assert_eq!(from_value::<Guess>(&compact).unwrap(), guess);
+    }
+
+    #[test]
+    fn round_trips_newtypes_enums_and_raw_values() {
+        round_trip(Score(42));
+        round_trip(Event::Started);
+        round_trip(Event::Guessed(Guess {
+            client_id: 1,
+            guess: 2,
+        }));
+        round_trip(Event::Won { client_id: 9 });
+        round_trip(rmpv::Value::Map(vec![(
+            rmpv::Value::from("guess"),
+            rmpv::Value::Array(vec![rmpv::Value::from(-1), rmpv::Value::from(2.5)]),
+        )]));
+
+        assert!(from_value::<Guess>(&rmpv::Value::from("nope")).is_err());
+    }
+}
@@ -1,166 +1,497 @@
+pub mod broker;
+mod client;
 mod codec;
 mod parsing;
 pub mod runtime;
+#[cfg(test)]
+mod testing;
+mod transport;
 
 pub use cliff_derive::*;
 
-use std::{env, fs, io::ErrorKind};
+use std::{
+    future::Future,
+    io,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
-use bytes::{buf::*, Bytes, BytesMut};
+use bytes::BytesMut;
 
-use failure::{Error, ResultExt};
+use failure::{format_err, Error, ResultExt};
 
-use futures::stream::StreamExt;
+use futures::{
+    future::{self, AbortHandle},
+    sink::SinkExt,
+    stream::StreamExt,
+};
 
 use rmpv;
 
-use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
 
 use tokio_util::codec::{Decoder, Encoder};
 
-use tokio::net::{UnixListener, UnixStream};
-use tokio::prelude::*;
-use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
-
-use tokio_util::codec::Framed;
-
-use parsing::{encode_value, MsgPackParser};
-
-use runtime::{Handled, Runtime, SelfStarter};
-pub use runtime::{Handler, Message};
-
-// TODO: Compolete Message Parsing
-pub struct MessageCodec<C: Encoder + Decoder, T> {
+use tokio::{
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    time,
+};
+
+use tokio_util::codec::{FramedRead, FramedWrite};
+
+use codec::{Beat, Control, Envelope, Gate, Liveness};
+use parsing::MsgPackParser;
+use transport::ReadHalf;
+
+pub use broker::{Broker, Pattern, Publication, Subscriber};
+pub use client::{
+    create_client, create_client_with, create_reconnecting_client, Client, ClientConfig,
+    ClientSink, ClientStream, ConnectionState, Reconnect, RemoteAddress, DEFAULT_REQUEST_TIMEOUT,
+};
+pub use codec::{
+    Capabilities, Capability, Heartbeat, Negotiated, Registered, Registry, Rejection, Secret,
+    Versions, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+pub use runtime::{
+    Actor, AsyncHandler, Clock, Handler, Mailbox, MailboxError, Message, Overflow, Request,
+    Responder, SendError, StopMode, Strategy, SupervisionEvent, Supervisor, TimerHandle,
+};
+use runtime::{Handled, Runtime};
+pub use transport::{
+    Allowlist, AlreadyRunning, Authorize, Endpoint, Listener, LockFile, PeerCredentials, SameUser,
+    Socket, CONFIG_VAR, ENDPOINT_VAR,
+};
+
+/// Frames actor messages on top of a raw value codec.
+///
+/// Values are wrapped in typed envelopes and resolved through `T`'s `Registry`.
+pub struct MessageCodec<C, T> {
     raw_codec: C,
-    runtime: Runtime<T>,
+    registry: Arc<Registry<T>>,
 }
 
-impl<C: Encoder + Decoder, T> Encoder for MessageCodec<C, T> {
+impl<C, T> MessageCodec<C, T> {
+    pub fn new(raw_codec: C, registry: Arc<Registry<T>>) -> Self {
+        Self {
+            raw_codec,
+            registry,
+        }
+    }
+}
+
+impl<C, T> Encoder for MessageCodec<C, T>
+where
+    C: Encoder<Item = rmpv::Value, Error = Error>,
+    T: 'static,
+{
     type Item = Box<dyn Handled<T>>;
     type Error = Error;
 
     fn encode(&mut self, item: Box<dyn Handled<T>>, dst: &mut BytesMut) -> Result<(), Error> {
-        unimplemented!()
+        let value = self.registry.encode(item.as_ref())?;
+
+        self.raw_codec.encode(value, dst)
     }
 }
 
-impl<C: Encoder + Decoder, T> Decoder for MessageCodec<C, T> {
+impl<C, T> Decoder for MessageCodec<C, T>
+where
+    C: Decoder<Item = rmpv::Value, Error = Error>,
+    T: 'static,
+{
     type Item = Box<dyn Handled<T>>;
     type Error = Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Box<dyn Handled<T>>>, Error> {
-        unimplemented!()
+        match self.raw_codec.decode(src)? {
+            Some(value) => self.registry.decode(value).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Pushes messages to a single connected peer.
+///
+/// Messages are encoded through the peer's `Registry`, `C` being the actor
+/// that handles them on the other end. Clones share the same connection.
+pub struct Outbound<C> {
+    sender: UnboundedSender<rmpv::Value>,
+    registry: Arc<Registry<C>>,
+}
+
+impl<C> Clone for Outbound<C> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            registry: self.registry.clone(),
+        }
+    }
+}
+
+impl<C: 'static> Outbound<C> {
+    /// Fails if `M` isn't registered for `C`, or the connection is gone.
+    pub fn send<M: Handled<C>>(&self, message: M) -> Result<(), Error> {
+        let value = self.registry.encode(&message)?;
+
+        self.sender
+            .send(value)
+            .map_err(|_| format_err!("The connection is closed"))
     }
 }
 
-pub struct UnixConnection(Option<UnixStream>);
-impl Message for UnixConnection {}
+/// Connections double as broker subscribers, forwarding publications to their peer.
+impl<C, E> Subscriber<E> for Outbound<C>
+where
+    C: 'static,
+    E: Send + Sync + 'static,
+    Publication<E>: Handled<C>,
+{
+    fn deliver(&self, publication: Publication<E>) -> bool {
+        self.send(publication).is_ok()
+    }
+}
+
+static CONNECTION_IDS: AtomicUsize = AtomicUsize::new(0);
+
+/// Tells a server's connections apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectionId(usize);
+
+/// Handed to a server actor for every client that connects, over whichever transport.
+///
+/// `C` is the client side actor, which decides what can be sent back.
+pub struct UnixConnection<C> {
+    id: ConnectionId,
+    name: String,
+    negotiated: Negotiated,
+    peer: Option<PeerCredentials>,
+    outbound: Outbound<C>,
+}
+impl<C> Message for UnixConnection<C> {}
+
+impl<C> UnixConnection<C> {
+    pub fn id(&self) -> ConnectionId {
+        self.id
+    }
+
+    /// What the client called itself during the handshake.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The protocol version and capabilities agreed on with the client.
+    pub fn negotiated(&self) -> &Negotiated {
+        &self.negotiated
+    }
+
+    /// Who connected, for connections over a Unix socket.
+    pub fn peer(&self) -> Option<PeerCredentials> {
+        self.peer
+    }
 
-impl UnixConnection {
-    pub fn take_socket(&mut self) -> Option<UnixStream> {
-        self.0.take()
+    pub fn outbound(&self) -> &Outbound<C> {
+        &self.outbound
     }
 }
 
-pub trait UnixServer: Sized {
-    fn serve() -> Runtime<Self>;
+/// Sent to a server actor once a connection closed, broke or missed its heartbeats.
+///
+/// Its `Outbound` handles fail from then on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConnectionLost(pub ConnectionId);
+impl Message for ConnectionLost {}
+
+pub trait UnixServer<C>: Sized {
+    fn serve_with(actor: Self) -> Runtime<Self>;
+    fn serve_async<F: Future<Output = Self> + Send + 'static>(init: F) -> Runtime<Self>;
+    /// Serves on `endpoint`, failing before `init` runs if it can't be bound.
+    fn serve_on<F: Future<Output = Self> + Send + 'static>(
+        endpoint: &Endpoint,
+        init: F,
+    ) -> Result<Runtime<Self>, Error>;
+    /// Serves on a `Listener` that's already bound, to pick its `Authorize` policy.
+    fn serve_from<F: Future<Output = Self> + Send + 'static>(
+        listener: Listener,
+        init: F,
+    ) -> Runtime<Self>;
+
+    fn serve() -> Runtime<Self>
+    where
+        Self: Default,
+    {
+        Self::serve_with(Self::default())
+    }
 }
 
-impl<T: Handler<UnixConnection> + Default + Send + 'static> UnixServer for T {
-    fn serve() -> Runtime<T> {
-        let runtime = T::start();
+impl<T, C> UnixServer<C> for T
+where
+    T: Handler<UnixConnection<C>> + Handler<ConnectionLost> + Registered + Actor,
+    C: Registered + 'static,
+{
+    fn serve_with(actor: T) -> Runtime<T> {
+        Self::serve_async(future::ready(actor))
+    }
+
+    fn serve_async<F: Future<Output = T> + Send + 'static>(init: F) -> Runtime<T> {
+        Endpoint::local()
+            .and_then(|endpoint| Self::serve_on(&endpoint, init))
+            .context("Failed to open listener")
+            .unwrap()
+    }
+
+    fn serve_on<F: Future<Output = T> + Send + 'static>(
+        endpoint: &Endpoint,
+        init: F,
+    ) -> Result<Runtime<T>, Error> {
+        Ok(Self::serve_from(Listener::bind(endpoint)?, init))
+    }
+
+    fn serve_from<F: Future<Output = T> + Send + 'static>(
+        listener: Listener,
+        init: F,
+    ) -> Runtime<T> {
+        let runtime = Runtime::spawn_async(init);
 
-        listen(&runtime);
+        listen::<T, C>(&runtime, listener);
 
         runtime
     }
 }
 
-fn listen<T: Handler<UnixConnection> + Default + Send + 'static>(runtime: &Runtime<T>) {
-    let mut listener = open_uds_listener()
-        .context("Failed to open Unix Listener")
-        .unwrap();
-    let cloned = runtime.clone();
+/// How long `listen` waits before accepting again after a failed accept.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+fn listen<T, C>(runtime: &Runtime<T>, mut listener: Listener)
+where
+    T: Handler<UnixConnection<C>> + Handler<ConnectionLost> + Registered + Actor,
+    C: Registered + 'static,
+{
+    let runtime = runtime.clone();
+    let registry = Arc::new(T::registry());
+    let peer_registry = Arc::new(C::registry());
+    let gate = listener.gate();
 
     tokio::spawn(async move {
-        let new_conn_stream = listener
-            .incoming()
-            .filter_map(|r: Result<_, _>| async { r.ok() })
-            .then(|socket| forward_parsed(&cloned, socket));
+        // Stop accepting connections once nobody is left to handle them
+        while runtime.is_connected() {
+            let socket = accept(&mut listener).await;
+
+            // Handshakes happen on their own, so a slow client can't hold up the others
+            tokio::spawn(admit::<T, C>(
+                runtime.clone(),
+                registry.clone(),
+                peer_registry.clone(),
+                gate.clone(),
+                socket,
+            ));
+        }
+    });
+}
 
-        let mut pinned = Box::pin(new_conn_stream);
-        while let Some(m) = pinned.next().await {
-            cloned.send(m);
+/// Waits for the next connection, riding out failed accepts.
+async fn accept(listener: &mut Listener) -> Socket {
+    loop {
+        match listener.accept().await {
+            Ok(socket) => return socket,
+            Err(e) => {
+                // Running out of file descriptors and the like passes, give it a moment
+                eprintln!("Couldn't accept a connection: {}", e);
+                time::delay_for(ACCEPT_BACKOFF).await;
+            }
         }
+    }
+}
+
+async fn admit<T, C>(
+    runtime: Runtime<T>,
+    registry: Arc<Registry<T>>,
+    peer_registry: Arc<Registry<C>>,
+    gate: Gate,
+    socket: Socket,
+) where
+    T: Handler<UnixConnection<C>> + Handler<ConnectionLost> + Registered + Actor,
+    C: Registered + 'static,
+{
+    let (connection, inbound) = match connect(socket, &peer_registry, &gate).await {
+        Ok(connected) => connected,
+        Err(_) => return,
+    };
+
+    // Clients that can't answer pings never get any
+    let heartbeat = T::heartbeat().filter(|_| {
+        let capabilities = &connection.negotiated().capabilities;
+        capabilities.contains(Capability::Heartbeat)
     });
+
+    // Only read once the actor knows about the connection
+    if runtime.send(connection).await.is_ok() {
+        forward_parsed(runtime, registry, inbound, heartbeat).await;
+    }
 }
 
-async fn forward_parsed<T: Handler<UnixConnection> + Default + Send + 'static>(
-    runtime: &Runtime<T>,
-    socket: UnixStream,
-) -> UnixConnection {
-    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
-    let runtime = runtime.clone();
+/// The read side of a server connection, along with what it needs to answer control frames.
+struct Inbound {
+    id: ConnectionId,
+    frames: FramedRead<ReadHalf, codec::MsgPackCodec>,
+    replies: UnboundedSender<rmpv::Value>,
+    writer: AbortHandle,
+}
 
-    tokio::spawn(async move {
-        // TODO: Handle the Write side of this equation.
-        // Need to figure out how to work with stream and subject
-        // separately and the borrow/lifetime implications
-        // NOTE: Trying to split these into different threads tends not to work out
-        // It'd be better to have a wrapping struct that owns the socket
-        // and manages the task splitting
-        // Seems like an answer: https://docs.rs/tokio-util/0.2.0/tokio_util/codec/struct.Framed.html
-        // As seen in: https://github.com/tokio-rs/tokio/issues/1840
-        // Also: https://docs.rs/futures-preview/0.3.0-alpha.19/futures/macro.select.html
-        let framed = Framed::new(
-            socket,
-            MessageCodec {
-                raw_codec: codec::MsgPackCodec {},
-                runtime: runtime.clone(),
+async fn connect<C>(
+    socket: Socket,
+    peer_registry: &Arc<Registry<C>>,
+    gate: &Gate,
+) -> Result<(UnixConnection<C>, Inbound), Error> {
+    let peer = socket.peer();
+    let (read_half, write_half) = socket.into_split();
+    // Only handshake sized frames are buffered for a client that isn't let in yet
+    let handshake = codec::MsgPackCodec::new().with_max_frame(codec::HANDSHAKE_MAX_FRAME);
+    let mut frames = FramedRead::new(read_half, handshake);
+    let mut outbound = FramedWrite::new(write_half, codec::MsgPackCodec::new());
+
+    // Nothing the client sends is decoded before it's let in
+    let admission = gate.admit(&mut frames, &mut outbound).await?;
+    *frames.decoder_mut() = codec::MsgPackCodec::new();
+
+    let id = ConnectionId(CONNECTION_IDS.fetch_add(1, Ordering::Relaxed));
+    let (sender, receiver) = unbounded_channel();
+    // Ends once every `Outbound` is dropped, the peer goes away, or it's aborted
+    let (writer, abort) = future::abortable(receiver.map(Ok).forward(outbound));
+    tokio::spawn(writer);
+
+    let connection = UnixConnection {
+        id,
+        name: admission.name,
+        negotiated: admission.negotiated,
+        peer,
+        outbound: Outbound {
+            sender: sender.clone(),
+            registry: peer_registry.clone(),
+        },
+    };
+    let inbound = Inbound {
+        id,
+        frames,
+        replies: sender,
+        writer: abort,
+    };
+
+    Ok((connection, inbound))
+}
+
+async fn forward_parsed<T: Handler<ConnectionLost> + Actor>(
+    runtime: Runtime<T>,
+    registry: Arc<Registry<T>>,
+    mut inbound: Inbound,
+    heartbeat: Option<Heartbeat>,
+) {
+    let mut liveness = Liveness::new(heartbeat);
+
+    loop {
+        tokio::select! {
+            frame = inbound.frames.next() => {
+                let value = match frame {
+                    Some(Ok(value)) => value,
+                    // The socket itself failed, nothing more will come through
+                    Some(Err(e)) if e.downcast_ref::<io::Error>().is_some() => break,
+                    // A single frame that couldn't be decoded
+                    Some(Err(_)) => continue,
+                    None => break,
+                };
+                liveness.saw_frame();
+
+                match Control::from_value(&value) {
+                    Some(Control::Ping) => {
+                        inbound.replies.send(Control::Pong.to_value()).ok();
+                    }
+                    Some(Control::Pong) => {}
+                    None => {
+                        let decoded = match Envelope::from_value(&value) {
+                            // Servers don't ask, and nobody waits on expired requests anymore
+                            Some(envelope) if envelope.reply_to.is_some() => continue,
+                            Some(envelope) if envelope.is_expired() => continue,
+                            Some(envelope) => {
+                                let id = envelope.id;
+                                let decoded = registry.decode_request(envelope, &inbound.replies);
+                                if decoded.is_err() {
+                                    // Answering with no body fails the ask now, not at its deadline
+                                    let dropped = Envelope::reply(id, None);
+                                    inbound.replies.send(dropped.to_value()).ok();
+                                }
+
+                                decoded
+                            }
+                            None => registry.decode(value),
+                        };
+
+                        match decoded {
+                            Ok(message) => {
+                                if message.be_forwaded(&runtime).await.is_err() {
+                                    break;
+                                }
+                            }
+                            // A single message that couldn't be decoded
+                            Err(_) => continue,
+                        }
+                    }
+                }
+            }
+            beat = liveness.tick() => match beat {
+                Beat::Ping => {
+                    inbound.replies.send(Control::Ping.to_value()).ok();
+                }
+                Beat::Dead => break,
             },
-        );
+        }
+    }
 
-        let (subject, mut stream) = framed.split();
-        let forwarded = rx.forward(subject);
+    inbound.writer.abort();
+    runtime.send(ConnectionLost(inbound.id)).await.ok();
+}
 
-        tokio::spawn(forwarded);
-        if let Some(Ok(next)) = stream.next().await {
-            next.be_forwaded(&runtime)
-        };
-    });
+// Server/Client
+/// Serves on the local endpoint, handing over every message clients send.
+///
+/// Messages are deserialized to `T` through rmp-serde, the ones that don't fit are dropped.
+pub fn create_server<T>() -> Result<UnboundedReceiver<T>, Error>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    serve_values(None)
+}
 
-    // TODO: Return tx here
-    UnixConnection(None)
+/// Like `create_server`, dropping clients that miss their heartbeats.
+pub fn create_server_with<T>(heartbeat: Heartbeat) -> Result<UnboundedReceiver<T>, Error>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    serve_values(Some(heartbeat))
 }
 
-// Server/Client
-pub fn create_server() -> Result<UnboundedReceiver<rmpv::Value>, Error> {
+fn serve_values<T>(heartbeat: Option<Heartbeat>) -> Result<UnboundedReceiver<T>, Error>
+where
+    T: DeserializeOwned + Send + 'static,
+{
     // TODO: Set up connection closing on close
     // 0. Set up
     let (tx, rx) = unbounded_channel();
 
-    // 1. Set up Unix listener
-    let mut unix_listener = open_uds_listener().context("Failed to open Unix Listener")?;
+    // 1. Set up listener
+    let mut listener = Endpoint::local()
+        .and_then(|endpoint| Listener::bind(&endpoint))
+        .context("Failed to open listener")?;
+
+    let gate = listener.gate();
 
     tokio::spawn({
         async move {
-            // TODO: Handle Errors in here
             loop {
-                let (mut socket, _) = unix_listener.accept().await.unwrap();
-
-                tokio::spawn({
-                    let tx = tx.clone();
+                let socket = accept(&mut listener).await;
 
-                    async move {
-                        let (read_stream, _) = socket.split();
-
-                        let mut parser = MsgPackParser::new(read_stream);
-
-                        while let Some(value) = parser.next().await {
-                            tx.send(value).ok();
-                        }
-                    }
-                });
+                tokio::spawn(read_values(socket, tx.clone(), heartbeat, gate.clone()));
             }
         }
     });
@@ -169,49 +500,404 @@ pub fn create_server() -> Result<UnboundedReceiver<rmpv::Value>, Error> {
     Ok(rx)
 }
 
-fn open_uds_listener() -> Result<UnixListener, Error> {
-    let path = get_uds_path()?;
-    match UnixListener::bind(&path) {
-        Ok(l) => Ok(l),
-        Err(e) if e.kind() == ErrorKind::AddrInUse => {
-            // 1. Handle cases where file exists
-            // TODO: Handle it more gracefully (Ask user whether to force or abort)
-            println!("A connection file already exists. Removing it.");
-            fs::remove_file(&path)?;
+async fn read_values<T: DeserializeOwned>(
+    socket: Socket,
+    tx: UnboundedSender<T>,
+    heartbeat: Option<Heartbeat>,
+    gate: Gate,
+) {
+    let (read_stream, write_stream) = socket.into_split();
+
+    let mut parser = MsgPackParser::new(read_stream);
+    let mut control = FramedWrite::new(write_stream, codec::MsgPackCodec::new());
+
+    let mut values = (&mut parser).map(Ok);
+    let admission = match gate.admit(&mut values, &mut control).await {
+        Ok(admission) => admission,
+        Err(_) => return,
+    };
+
+    let capabilities = admission.negotiated.capabilities;
+    let heartbeat = heartbeat.filter(|_| capabilities.contains(Capability::Heartbeat));
+    let mut liveness = Liveness::new(heartbeat);
+
+    loop {
+        let reply = tokio::select! {
+            value = parser.next() => {
+                let value = match value {
+                    Some(value) => value,
+                    None => break,
+                };
+                liveness.saw_frame();
+
+                match Control::from_value(&value) {
+                    Some(Control::Ping) => Control::Pong,
+                    Some(Control::Pong) => continue,
+                    None => {
+                        if let Ok(message) = codec::from_value(&value) {
+                            tx.send(message).ok();
+                        }
+                        continue;
+                    }
+                }
+            }
+            beat = liveness.tick() => match beat {
+                Beat::Ping => Control::Ping,
+                Beat::Dead => break,
+            },
+        };
 
-            UnixListener::bind(&path).map_err(Error::from)
+        if control.send(reply.to_value()).await.is_err() {
+            break;
         }
-        Err(e) => Err(Error::from(e)),
     }
 }
 
-pub async fn create_client() -> Result<UnboundedSender<rmpv::Value>, Error> {
-    let addr = get_uds_path()?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let (tx, mut rx) = unbounded_channel::<rmpv::Value>();
-    let mut stream = UnixStream::connect(addr).await?;
+    use std::time::Duration;
 
-    tokio::spawn(async move {
-        while let Some(message) = rx.recv().await {
-            let encoded = encode_value(&message);
-            let mut buffer = Bytes::from(encoded);
-
-            while !buffer.is_empty() {
-                if let Err(e) = stream.write_buf(&mut buffer).await {
-                    println!("Sending error: {}", e);
-                    println!(
-                        "Trying to send message: {}",
-                        String::from_utf8(buffer.to_vec()).unwrap()
-                    );
-                }
+    use tokio_util::codec::Framed;
+
+    use codec::Identity;
+    use runtime::{Request, Responder};
+    use testing::{Add, Count, Counter};
+
+    impl Handler<UnixConnection<Counter>> for Counter {
+        fn handle(&mut self, message: &mut UnixConnection<Counter>) {
+            message.outbound().send(Add(self.count)).ok();
+        }
+    }
+
+    impl Handler<ConnectionLost> for Counter {
+        fn handle(&mut self, message: &mut ConnectionLost) {
+            self.lost.push(message.0);
+        }
+    }
+
+    struct Lost;
+    impl Message for Lost {}
+    impl Request for Lost {
+        type Result = Vec<ConnectionId>;
+    }
+
+    impl Responder<Lost> for Counter {
+        fn respond(&mut self, _: &mut Lost) -> Vec<ConnectionId> {
+            self.lost.clone()
+        }
+    }
+
+    type Reader = FramedRead<ReadHalf, codec::MsgPackCodec>;
+    type Writer = FramedWrite<transport::WriteHalf, codec::MsgPackCodec>;
+
+    fn framed(socket: Socket) -> (Reader, Writer) {
+        let (read_half, write_half) = socket.into_split();
+
+        (
+            FramedRead::new(read_half, codec::MsgPackCodec::new()),
+            FramedWrite::new(write_half, codec::MsgPackCodec::new()),
+        )
+    }
+
+    fn identity(secret: Option<&str>) -> Identity {
+        Identity::new("counter".to_string(), secret.map(Secret::new))
+    }
+
+    /// Connects both ends of a socket pair, handshake included.
+    async fn connected(
+        registry: &Arc<Registry<Counter>>,
+    ) -> (UnixConnection<Counter>, Inbound, Reader, Writer) {
+        let (server_side, client_side) = tokio::net::UnixStream::pair().unwrap();
+        let (mut reader, mut writer) = framed(client_side.into());
+        let gate = Gate::new(None, true);
+        let client = identity(None);
+
+        let (connected, introduced) = tokio::join!(
+            connect(server_side.into(), registry, &gate),
+            client.introduce(&mut reader, &mut writer)
+        );
+        introduced.unwrap();
+        let (connection, inbound) = connected.unwrap();
+
+        (connection, inbound, reader, writer)
+    }
+
+    async fn wait_for_count(server: &Runtime<Counter>, expected: u32) {
+        // Inbound messages take their own path through the socket
+        let mut count = 0;
+        for _ in 0..100 {
+            count = server.ask(Count).await.unwrap();
+            if count == expected {
+                break;
             }
+            tokio::time::delay_for(Duration::from_millis(5)).await;
         }
-    });
+        assert_eq!(count, expected);
+    }
 
-    Ok(tx)
-}
+    #[tokio::test]
+    async fn connections_forward_every_message_and_reply() {
+        let server = Runtime::spawn(Counter::default());
+        let registry = Arc::new(Counter::registry());
+
+        let (connection, inbound, mut reader, mut writer) = connected(&registry).await;
+        tokio::spawn(forward_parsed(
+            server.clone(),
+            registry.clone(),
+            inbound,
+            None,
+        ));
+        assert_eq!(connection.name(), "counter");
+        assert_eq!(connection.negotiated().version, PROTOCOL_VERSION);
+        assert!(SameUser.authorize(&connection.peer().unwrap()));
+
+        for i in 1..=3 {
+            let value = registry.encode(&Add(i)).unwrap();
+            writer.send(value).await.unwrap();
+        }
+
+        connection.outbound().send(Add(7)).unwrap();
+        let reply = registry.decode(reader.next().await.unwrap().unwrap());
+        assert_eq!(reply.unwrap().as_any().downcast_ref::<Add>().unwrap().0, 7);
+
+        wait_for_count(&server, 6).await;
+    }
+
+    fn message_codec(
+        registry: &Arc<Registry<Counter>>,
+    ) -> MessageCodec<codec::MsgPackCodec, Counter> {
+        MessageCodec::new(codec::MsgPackCodec::new(), registry.clone())
+    }
+
+    #[tokio::test]
+    async fn message_codecs_round_trip_typed_messages() {
+        let registry = Arc::new(Counter::registry());
+        let (left, right) = tokio::net::UnixStream::pair().unwrap();
+        let mut sender = Framed::new(left, message_codec(&registry));
+        let mut receiver = Framed::new(right, message_codec(&registry));
+
+        sender.send(Box::new(Add(3))).await.unwrap();
+        sender.send(Box::new(Add(4))).await.unwrap();
+
+        let mut counter = Counter::default();
+        for _ in 0..2 {
+            let mut message = receiver.next().await.unwrap().unwrap();
+            assert!(message.as_any().is::<Add>());
+            message.be_handled(&mut counter).await;
+        }
+        assert_eq!(counter.count, 7);
+
+        let unregistered: Box<dyn Handled<Counter>> = Box::new(Reset);
+        assert!(sender.send(unregistered).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn message_codecs_reject_unknown_or_malformed_frames() {
+        let registry = Arc::new(Counter::registry());
+        let (left, right) = tokio::net::UnixStream::pair().unwrap();
+        let mut sender = Framed::new(left, codec::MsgPackCodec::new());
+        let mut receiver = Framed::new(right, message_codec(&registry));
+
+        let unknown = rmpv::Value::Array(vec!["Sub".into(), 1.into()]);
+        sender.send(unknown).await.unwrap();
+        assert!(receiver.next().await.unwrap().is_err());
+
+        let mistyped = rmpv::Value::Array(vec!["Add".into(), "one".into()]);
+        sender.send(mistyped).await.unwrap();
+        assert!(receiver.next().await.unwrap().is_err());
+
+        sender.send(rmpv::Value::from("Add")).await.unwrap();
+        assert!(receiver.next().await.unwrap().is_err());
+    }
 
-fn get_uds_path() -> Result<String, Error> {
-    let home = env::var("HOME").context("Couldn't retrieve HOME env var")?;
-    Ok(format!("{}/.central/.sock", home))
+    #[tokio::test]
+    async fn serves_over_tcp() {
+        let listener = Listener::bind(&Endpoint::Tcp("127.0.0.1:0".parse().unwrap()))
+            .unwrap()
+            .with_secret(Secret::new("meow"));
+        let endpoint = listener.local_endpoint().unwrap();
+        let server = Runtime::spawn(Counter::default());
+        listen::<Counter, Counter>(&server, listener);
+
+        let (mut reader, mut writer) = framed(Socket::connect(&endpoint).await.unwrap());
+        identity(Some("meow"))
+            .introduce(&mut reader, &mut writer)
+            .await
+            .unwrap();
+        let registry = Arc::new(Counter::registry());
+
+        // Greeted with the count as it was when connecting
+        let greeting = registry.decode(reader.next().await.unwrap().unwrap());
+        assert_eq!(
+            greeting.unwrap().as_any().downcast_ref::<Add>().unwrap().0,
+            0
+        );
+
+        writer
+            .send(registry.encode(&Add(5)).unwrap())
+            .await
+            .unwrap();
+        wait_for_count(&server, 5).await;
+    }
+
+    #[tokio::test]
+    async fn turns_away_tcp_clients_without_the_secret() {
+        let listener = Listener::bind(&Endpoint::Tcp("127.0.0.1:0".parse().unwrap())).unwrap();
+        let endpoint = listener.local_endpoint().unwrap();
+        let server = Runtime::spawn(Counter::default());
+        listen::<Counter, Counter>(&server, listener);
+
+        let (mut reader, mut writer) = framed(Socket::connect(&endpoint).await.unwrap());
+        let introduced = identity(None).introduce(&mut reader, &mut writer).await;
+
+        assert_eq!(
+            introduced.unwrap_err().downcast_ref::<Rejection>(),
+            Some(&Rejection::Unauthorized)
+        );
+        assert!(reader.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn silent_connections_are_lost() {
+        let server = Runtime::spawn(Counter::default());
+        let registry = Arc::new(Counter::registry());
+
+        let (connection, inbound, mut reader, _writer) = connected(&registry).await;
+        let heartbeat = Heartbeat::new(Duration::from_millis(10), Duration::from_millis(30));
+        let forwarding = tokio::spawn(forward_parsed(
+            server.clone(),
+            registry.clone(),
+            inbound,
+            Some(heartbeat),
+        ));
+
+        let ping = reader.next().await.unwrap().unwrap();
+        assert_eq!(Control::from_value(&ping), Some(Control::Ping));
+
+        forwarding.await.unwrap();
+        assert_eq!(server.ask(Lost).await.unwrap(), vec![connection.id()]);
+        assert!(connection.outbound().send(Add(1)).is_err());
+    }
+
+    #[tokio::test]
+    async fn remote_addresses_send_and_ask() {
+        let listener = Listener::bind(&Endpoint::Tcp("127.0.0.1:0".parse().unwrap()))
+            .unwrap()
+            .with_secret(Secret::new("meow"));
+        let config = ClientConfig::new()
+            .with_endpoint(listener.local_endpoint().unwrap())
+            .with_secret(Secret::new("meow"));
+        let server = Runtime::spawn(Counter::default());
+        listen::<Counter, Counter>(&server, listener);
+
+        let remote = RemoteAddress::<Counter>::connect(config).await.unwrap();
+        remote.send(Add(2)).await.unwrap();
+        remote.send(Add(3)).await.unwrap();
+
+        let (first, second) = tokio::join!(remote.ask(Count), remote.ask(Count));
+        assert_eq!((first.unwrap(), second.unwrap()), (5, 5));
+        wait_for_count(&server, 5).await;
+    }
+
+    struct Reset;
+    impl Message for Reset {}
+
+    impl Handler<Reset> for Counter {
+        fn handle(&mut self, _: &mut Reset) {
+            self.count = 0;
+        }
+    }
+
+    #[tokio::test]
+    async fn answers_undecodable_requests_right_away() {
+        let server = Runtime::spawn(Counter::default());
+        let registry = Arc::new(Counter::registry());
+
+        let (_connection, inbound, mut reader, mut writer) = connected(&registry).await;
+        tokio::spawn(forward_parsed(
+            server.clone(),
+            registry.clone(),
+            inbound,
+            None,
+        ));
+
+        let body = rmpv::Value::Array(vec!["Remove".into(), 3.into()]);
+        let request = Envelope::request(body, Some(Duration::from_secs(60)));
+        writer.send(request.to_value()).await.unwrap();
+
+        let reply = Envelope::from_value(&reader.next().await.unwrap().unwrap()).unwrap();
+        assert_eq!(reply.reply_to, Some(request.id));
+        assert_eq!(reply.body, None);
+    }
+
+    #[tokio::test]
+    async fn oversized_frames_end_the_connection() {
+        use tokio::io::AsyncWriteExt;
+
+        let server = Runtime::spawn(Counter::default());
+        let registry = Arc::new(Counter::registry());
+
+        let (connection, inbound, _reader, mut writer) = connected(&registry).await;
+        let forwarding = tokio::spawn(forward_parsed(
+            server.clone(),
+            registry.clone(),
+            inbound,
+            None,
+        ));
+
+        // A bin(32) header claiming 4 GiB of payload
+        let header = [0xc6, 0xff, 0xff, 0xff, 0xff];
+        writer.get_mut().write_all(&header).await.unwrap();
+
+        time::timeout(Duration::from_secs(1), forwarding)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(server.ask(Lost).await.unwrap(), vec![connection.id()]);
+    }
+
+    #[tokio::test]
+    async fn drops_clients_sending_large_frames_before_the_handshake() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = Listener::bind(&Endpoint::Tcp("127.0.0.1:0".parse().unwrap()))
+            .unwrap()
+            .with_secret(Secret::new("meow"));
+        let address = match listener.local_endpoint().unwrap() {
+            Endpoint::Tcp(address) => address,
+            other => panic!("Expected a TCP endpoint, got {}", other),
+        };
+        let server = Runtime::spawn(Counter::default());
+        listen::<Counter, Counter>(&server, listener);
+
+        // A bin(16) header for 5000 bytes, well within what admitted clients may send
+        let mut socket = tokio::net::TcpStream::connect(address).await.unwrap();
+        socket.write_all(&[0xc5, 0x13, 0x88]).await.unwrap();
+
+        let mut received = vec![];
+        let closed = time::timeout(Duration::from_secs(1), socket.read_to_end(&mut received));
+        assert!(closed.await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn remote_addresses_hand_back_unregistered_messages() {
+        let listener = Listener::bind(&Endpoint::Tcp("127.0.0.1:0".parse().unwrap()))
+            .unwrap()
+            .with_secret(Secret::new("meow"));
+        let config = ClientConfig::new()
+            .with_endpoint(listener.local_endpoint().unwrap())
+            .with_secret(Secret::new("meow"));
+        let server = Runtime::spawn(Counter::default());
+        listen::<Counter, Counter>(&server, listener);
+
+        let remote = RemoteAddress::<Counter>::connect(config).await.unwrap();
+
+        match remote.send(Reset).await {
+            Err(SendError::Unencodable(Reset)) => {}
+            other => panic!("Expected the message back, got {:?}", other),
+        }
+        assert!(remote.is_connected());
+    }
 }
@@ -6,7 +6,7 @@ use failure::{Error, ResultExt};
 
 use futures::stream::Stream;
 
-use rmpv::{self, decode::value::read_value, encode::write_value};
+use rmpv::{self, decode::value::read_value};
 
 enum DataSize {
     S8,
@@ -212,11 +212,3 @@ impl<R: tokio::io::AsyncRead + std::marker::Unpin> Stream for MsgPackParser<R> {
         Poll::Pending
     }
 }
-
-pub fn encode_value(value: &rmpv::Value) -> Vec<u8> {
-    let mut encoded = Vec::new();
-
-    write_value(&mut encoded, value).ok();
-
-    encoded
-}
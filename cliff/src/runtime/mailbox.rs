@@ -0,0 +1,361 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, MutexGuard, Weak},
+};
+
+use tokio::sync::Notify;
+
+use super::{Handled, MailboxError};
+
+/// What a bounded mailbox does with a message that arrives while it's full.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    /// Wait for the actor to free up a slot.
+    Wait,
+    /// Discard the incoming message.
+    DropNewest,
+    /// Discard the oldest queued message to make room for the incoming one.
+    DropOldest,
+    /// Reject the incoming message with `MailboxError::Full`.
+    Fail,
+}
+
+/// Describes the queue sitting in front of an actor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mailbox {
+    capacity: Option<usize>,
+    overflow: Overflow,
+}
+
+impl Mailbox {
+    pub fn unbounded() -> Self {
+        Self {
+            capacity: None,
+            overflow: Overflow::Wait,
+        }
+    }
+
+    pub fn bounded(capacity: usize, overflow: Overflow) -> Self {
+        assert!(capacity > 0, "A bounded mailbox needs room for one message");
+
+        Self {
+            capacity: Some(capacity),
+            overflow,
+        }
+    }
+
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    pub fn overflow(&self) -> Overflow {
+        self.overflow
+    }
+
+    pub(crate) fn open<T>(self) -> (Sender<T>, Receiver<T>) {
+        let shared = Arc::new(Shared {
+            config: self,
+            state: Mutex::new(State {
+                queue: VecDeque::new(),
+                closed: false,
+                senders: 1,
+            }),
+            arrived: Notify::new(),
+            freed: Notify::new(),
+        });
+
+        (
+            Sender {
+                shared: shared.clone(),
+            },
+            Receiver { shared },
+        )
+    }
+}
+
+impl Default for Mailbox {
+    fn default() -> Self {
+        Self::unbounded()
+    }
+}
+
+type Letter<T> = Box<dyn Handled<T> + Send>;
+
+struct State<T> {
+    queue: VecDeque<Letter<T>>,
+    closed: bool,
+    senders: usize,
+}
+
+struct Shared<T> {
+    config: Mailbox,
+    state: Mutex<State<T>>,
+    // Wakes the receiver when a message arrives or the last sender leaves
+    arrived: Notify,
+    // Wakes a sender waiting for room, or for the mailbox to close
+    freed: Notify,
+}
+
+impl<T> Shared<T> {
+    fn state(&self) -> MutexGuard<'_, State<T>> {
+        // A panicking handler never holds this lock, so poisoning can't leave a broken queue
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+pub(crate) struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    pub(crate) async fn push(&self, letter: Letter<T>) -> Result<(), (MailboxError, Letter<T>)> {
+        let mut letter = letter;
+        loop {
+            match self.try_push(letter) {
+                Err((MailboxError::Full, returned))
+                    if self.shared.config.overflow == Overflow::Wait =>
+                {
+                    letter = returned;
+                    self.shared.freed.notified().await;
+                }
+                Err((MailboxError::Closed, returned)) => {
+                    // Pass the wake up along so every waiting sender sees the mailbox closed
+                    self.shared.freed.notify();
+
+                    return Err((MailboxError::Closed, returned));
+                }
+                result => return result,
+            }
+        }
+    }
+
+    pub(crate) fn try_push(&self, letter: Letter<T>) -> Result<(), (MailboxError, Letter<T>)> {
+        let mut state = self.shared.state();
+        if state.closed {
+            return Err((MailboxError::Closed, letter));
+        }
+
+        let mut dropped = None;
+        match self.shared.config.capacity {
+            Some(capacity) if state.queue.len() >= capacity => match self.shared.config.overflow {
+                Overflow::Wait | Overflow::Fail => return Err((MailboxError::Full, letter)),
+                Overflow::DropNewest => dropped = Some(letter),
+                Overflow::DropOldest => {
+                    dropped = state.queue.pop_front();
+                    state.queue.push_back(letter);
+                }
+            },
+            _ => state.queue.push_back(letter),
+        }
+        drop(state);
+        // Dropped outside the lock, as messages may hold senders of their own
+        drop(dropped);
+
+        self.shared.arrived.notify();
+
+        Ok(())
+    }
+
+    /// A handle that doesn't keep the mailbox open on its own.
+    pub(crate) fn downgrade(&self) -> WeakSender<T> {
+        WeakSender {
+            shared: Arc::downgrade(&self.shared),
+        }
+    }
+
+    /// Rejects new messages, the queued ones staying available to the receiver.
+    pub(crate) fn close(&self) {
+        self.shared.state().closed = true;
+        self.shared.arrived.notify();
+        self.shared.freed.notify();
+    }
+
+    pub(crate) fn is_open(&self) -> bool {
+        !self.shared.state().closed
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.shared.state().queue.len()
+    }
+
+    pub(crate) fn config(&self) -> Mailbox {
+        self.shared.config
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.state().senders += 1;
+
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state();
+        state.senders -= 1;
+
+        if state.senders == 0 {
+            drop(state);
+            self.shared.arrived.notify();
+        }
+    }
+}
+
+pub(crate) struct WeakSender<T> {
+    shared: Weak<Shared<T>>,
+}
+
+impl<T> WeakSender<T> {
+    /// Fails once every other sender is gone or the mailbox was closed.
+    pub(crate) fn upgrade(&self) -> Option<Sender<T>> {
+        let shared = self.shared.upgrade()?;
+
+        let mut state = shared.state();
+        if state.closed || state.senders == 0 {
+            return None;
+        }
+        state.senders += 1;
+        drop(state);
+
+        Some(Sender { shared })
+    }
+}
+
+pub(crate) struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Resolves to `None` once the mailbox is closed and empty, or every sender is gone.
+    pub(crate) async fn recv(&mut self) -> Option<Letter<T>> {
+        loop {
+            {
+                let mut state = self.shared.state();
+                if let Some(letter) = state.queue.pop_front() {
+                    drop(state);
+                    self.shared.freed.notify();
+
+                    return Some(letter);
+                }
+
+                if state.closed || state.senders == 0 {
+                    return None;
+                }
+            }
+
+            self.shared.arrived.notified().await;
+        }
+    }
+
+    /// Rejects new messages while keeping the queued ones available to `recv`.
+    pub(crate) fn close(&mut self) {
+        self.shared.state().closed = true;
+        self.shared.freed.notify();
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let queued = {
+            let mut state = self.shared.state();
+            state.closed = true;
+
+            state.queue.drain(..).collect::<Vec<_>>()
+        };
+        // Dropped outside the lock, as messages may hold senders of their own
+        drop(queued);
+
+        self.shared.freed.notify();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    use crate::runtime::{Actor, Handler, Message};
+
+    struct Sink;
+    impl Actor for Sink {}
+
+    struct Ping(u32);
+    impl Message for Ping {}
+
+    impl Handler<Ping> for Sink {
+        fn handle(&mut self, _: &mut Ping) {}
+    }
+
+    fn id(letter: Letter<Sink>) -> u32 {
+        letter.as_any().downcast_ref::<Ping>().unwrap().0
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_keeps_newest_messages() {
+        let (sender, mut receiver) = Mailbox::bounded(2, Overflow::DropOldest).open::<Sink>();
+
+        for i in 0..4 {
+            assert!(sender.push(Box::new(Ping(i))).await.is_ok());
+        }
+
+        assert_eq!(sender.len(), 2);
+        assert_eq!(id(receiver.recv().await.unwrap()), 2);
+        assert_eq!(id(receiver.recv().await.unwrap()), 3);
+    }
+
+    #[tokio::test]
+    async fn drop_newest_keeps_oldest_messages() {
+        let (sender, mut receiver) = Mailbox::bounded(1, Overflow::DropNewest).open::<Sink>();
+
+        assert!(sender.push(Box::new(Ping(0))).await.is_ok());
+        assert!(sender.push(Box::new(Ping(1))).await.is_ok());
+
+        assert_eq!(sender.len(), 1);
+        assert_eq!(id(receiver.recv().await.unwrap()), 0);
+    }
+
+    #[tokio::test]
+    async fn fail_rejects_when_full() {
+        let (sender, _receiver) = Mailbox::bounded(1, Overflow::Fail).open::<Sink>();
+
+        assert!(sender.push(Box::new(Ping(0))).await.is_ok());
+
+        let rejected = sender.push(Box::new(Ping(1))).await.unwrap_err();
+        assert_eq!(rejected.0, MailboxError::Full);
+        assert_eq!(id(rejected.1), 1);
+    }
+
+    #[tokio::test]
+    async fn wait_resumes_once_room_frees_up() {
+        let (sender, mut receiver) = Mailbox::bounded(1, Overflow::Wait).open::<Sink>();
+
+        assert!(sender.push(Box::new(Ping(0))).await.is_ok());
+
+        let waiting = tokio::spawn(async move {
+            assert!(sender.push(Box::new(Ping(1))).await.is_ok());
+            sender
+        });
+
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+        assert_eq!(id(receiver.recv().await.unwrap()), 0);
+
+        let sender = waiting.await.unwrap();
+        assert_eq!(sender.len(), 1);
+        assert_eq!(id(receiver.recv().await.unwrap()), 1);
+    }
+
+    #[tokio::test]
+    async fn closed_mailbox_rejects_messages() {
+        let (sender, mut receiver) = Mailbox::unbounded().open::<Sink>();
+
+        receiver.close();
+
+        let rejected = sender.push(Box::new(Ping(0))).await.unwrap_err();
+        assert_eq!(rejected.0, MailboxError::Closed);
+    }
+}
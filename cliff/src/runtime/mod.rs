@@ -1,63 +1,363 @@
-use std::ops::Deref;
+use std::{any::Any, error::Error, fmt, future::Future, ops::Deref, panic::AssertUnwindSafe};
 
 use serde::{Deserialize, Serialize};
 
-use tokio::sync::mpsc;
+use futures::future::{self, BoxFuture, FutureExt};
+
+use tokio::sync::{mpsc, oneshot, watch};
+
+mod mailbox;
+mod supervisor;
+mod timer;
+
+pub use mailbox::{Mailbox, Overflow};
+pub use supervisor::{Strategy, SupervisionEvent, Supervisor};
+pub use timer::{Clock, TimerHandle};
 
 // Runtime
-pub trait Message: Send + Sync {}
+/// Lifecycle hooks for everything that runs inside a `Runtime`.
+pub trait Actor: Send + Sized + 'static {
+    /// Called before the first message is handled.
+    fn started(&mut self) {}
+
+    /// Called once the actor is asked to stop or its last `Address` is dropped.
+    fn stopping(&mut self) {}
+
+    /// Called after the last message was handled, right before the actor is dropped.
+    fn stopped(&mut self) {}
+
+    /// The mailbox messages queue up in. Unbounded unless overridden.
+    fn mailbox() -> Mailbox {
+        Mailbox::unbounded()
+    }
+}
+
+pub trait Message: Send + Sync {
+    /// Names the message type the same way in every process it's built into.
+    ///
+    /// `#[derive(Message)]` makes it `Name`, or `namespace:Name` under a
+    /// `#[namespace("...")]`. Falls back to the type's path, which isn't
+    /// guaranteed to stay the same between compiler versions.
+    fn message_type() -> &'static str
+    where
+        Self: Sized,
+    {
+        std::any::type_name::<Self>()
+    }
+}
 
 pub trait Handler<M: Message> {
     fn handle(&mut self, message: &mut M);
 }
 
+/// Handles a message asynchronously.
+///
+/// The returned future may borrow the actor and is awaited to completion
+/// before the next message is taken from the mailbox.
+///
+/// ```ignore
+/// impl AsyncHandler<Load> for Projects {
+///     fn handle_async<'a>(&'a mut self, message: &'a mut Load) -> BoxFuture<'a, ()> {
+///         Box::pin(async move {
+///             self.projects = self.store.load(message.0).await;
+///         })
+///     }
+/// }
+/// ```
+pub trait AsyncHandler<M: Message> {
+    fn handle_async<'a>(&'a mut self, message: &'a mut M) -> BoxFuture<'a, ()>;
+}
+
+impl<T: Handler<M>, M: Message> AsyncHandler<M> for T {
+    fn handle_async<'a>(&'a mut self, message: &'a mut M) -> BoxFuture<'a, ()> {
+        Handler::handle(self, message);
+
+        Box::pin(future::ready(()))
+    }
+}
+
+/// A message the handling actor answers with a `Result`.
+pub trait Request: Message {
+    type Result: Send + 'static;
+}
+
+/// Like `Handler`, but produces a reply that is sent back to the asker.
+pub trait Responder<M: Request> {
+    fn respond(&mut self, message: &mut M) -> M::Result;
+}
+
+/// Wraps a `Request` together with the channel its reply is sent through.
+pub struct Ask<M: Request> {
+    message: M,
+    reply: Option<oneshot::Sender<M::Result>>,
+}
+impl<M: Request> Message for Ask<M> {}
+
+impl<M: Request> Ask<M> {
+    pub(crate) fn new(message: M, reply: oneshot::Sender<M::Result>) -> Self {
+        Self {
+            message,
+            reply: Some(reply),
+        }
+    }
+}
+
+impl<T: Responder<M>, M: Request> Handler<Ask<M>> for T {
+    fn handle(&mut self, ask: &mut Ask<M>) {
+        let result = self.respond(&mut ask.message);
+
+        if let Some(reply) = ask.reply.take() {
+            // The asker may have stopped waiting, nothing left to do then
+            reply.send(result).ok();
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MailboxError {
+    Closed,
+    Full,
+    Canceled,
+    /// No reply came before the request's deadline.
+    TimedOut,
+    /// The request couldn't be encoded for a connection, e.g. as it isn't registered.
+    Unencodable,
+}
+
+impl fmt::Display for MailboxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MailboxError::Closed => write!(f, "Actor's mailbox is closed"),
+            MailboxError::Full => write!(f, "Actor's mailbox is full"),
+            MailboxError::Canceled => write!(f, "Actor dropped the request without replying"),
+            MailboxError::TimedOut => write!(f, "Actor didn't reply before the deadline"),
+            MailboxError::Unencodable => write!(f, "Message couldn't be encoded for the actor"),
+        }
+    }
+}
+
+impl Error for MailboxError {}
+
+/// A message that couldn't be delivered, handed back to the sender.
+pub enum SendError<M> {
+    /// The actor stopped, or is stopping, and won't take new messages.
+    Closed(M),
+    /// The actor's bounded mailbox has no room left.
+    Full(M),
+    /// The message couldn't be encoded for a connection, e.g. as it isn't registered.
+    Unencodable(M),
+}
+
+impl<M> SendError<M> {
+    pub fn into_inner(self) -> M {
+        match self {
+            SendError::Closed(message)
+            | SendError::Full(message)
+            | SendError::Unencodable(message) => message,
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        match self {
+            SendError::Closed(_) => true,
+            SendError::Full(_) | SendError::Unencodable(_) => false,
+        }
+    }
+
+    fn map<N>(self, f: impl FnOnce(M) -> N) -> SendError<N> {
+        match self {
+            SendError::Closed(message) => SendError::Closed(f(message)),
+            SendError::Full(message) => SendError::Full(f(message)),
+            SendError::Unencodable(message) => SendError::Unencodable(f(message)),
+        }
+    }
+}
+
+impl<M: 'static> SendError<M> {
+    fn from_letter<T>(error: MailboxError, letter: Box<dyn Handled<T> + Send>) -> Self {
+        let message = match letter.into_any().downcast::<M>() {
+            Ok(message) => *message,
+            Err(_) => unreachable!("Invariant Violation: Mailbox returned another message"),
+        };
+
+        match error {
+            MailboxError::Full => SendError::Full(message),
+            _ => SendError::Closed(message),
+        }
+    }
+}
+
+impl<M> fmt::Debug for SendError<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::Closed(_) => write!(f, "SendError::Closed(..)"),
+            SendError::Full(_) => write!(f, "SendError::Full(..)"),
+            SendError::Unencodable(_) => write!(f, "SendError::Unencodable(..)"),
+        }
+    }
+}
+
+impl<M> fmt::Display for SendError<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::Closed(_) => write!(f, "{}", MailboxError::Closed),
+            SendError::Full(_) => write!(f, "{}", MailboxError::Full),
+            SendError::Unencodable(_) => write!(f, "{}", MailboxError::Unencodable),
+        }
+    }
+}
+
+impl<M> Error for SendError<M> {}
+
+impl<M> From<SendError<M>> for MailboxError {
+    fn from(error: SendError<M>) -> Self {
+        match error {
+            SendError::Closed(_) => MailboxError::Closed,
+            SendError::Full(_) => MailboxError::Full,
+            SendError::Unencodable(_) => MailboxError::Unencodable,
+        }
+    }
+}
+
 pub trait Handled<T>: Message {
-    fn be_handled(&mut self, actor: &mut T);
-    fn be_forwaded(self: Box<Self>, runtime: &Runtime<T>);
+    fn be_handled<'a>(&'a mut self, actor: &'a mut T) -> BoxFuture<'a, ()>;
+    fn be_forwaded(
+        self: Box<Self>,
+        runtime: &Runtime<T>,
+    ) -> BoxFuture<'_, Result<(), MailboxError>>;
+    fn as_any(&self) -> &dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any + Send>;
 }
 
-impl<T: Default + Send + 'static, M: Message + 'static> Handled<T> for M
+impl<T: Actor, M: Message + 'static> Handled<T> for M
 where
-    T: Handler<M>,
+    T: AsyncHandler<M>,
 {
-    fn be_handled(&mut self, actor: &mut T) {
-        actor.handle(self);
+    fn be_handled<'a>(&'a mut self, actor: &'a mut T) -> BoxFuture<'a, ()> {
+        actor.handle_async(self)
+    }
+
+    fn be_forwaded(
+        self: Box<Self>,
+        runtime: &Runtime<T>,
+    ) -> BoxFuture<'_, Result<(), MailboxError>> {
+        Box::pin(async move { Ok(runtime.forward(self).await?) })
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
     }
 
-    fn be_forwaded(self: Box<Self>, runtime: &Runtime<T>) {
-        runtime.forward(self)
+    fn into_any(self: Box<Self>) -> Box<dyn Any + Send> {
+        self
     }
 }
 
-pub struct Handle<T>(mpsc::UnboundedReceiver<Box<dyn Handled<T> + Send>>);
+pub struct Handle<T>(mailbox::Receiver<T>);
+
+pub struct Address<T>(mailbox::Sender<T>);
+
+impl<T> Clone for Address<T> {
+    fn clone(&self) -> Self {
+        Address(self.0.clone())
+    }
+}
 
-pub struct Address<T>(mpsc::UnboundedSender<Box<dyn Handled<T> + Send>>);
 impl<T> Address<T> {
-    pub fn send<M: Handled<T> + Send + Sync + 'static>(&self, message: M) {
-        // TODO: Better Error Handling
-        self.0.send(Box::new(message)).ok();
+    /// Queues `message`, waiting for room if the mailbox is full and set to `Overflow::Wait`.
+    pub async fn send<M: Handled<T> + Send + Sync + 'static>(
+        &self,
+        message: M,
+    ) -> Result<(), SendError<M>> {
+        self.0
+            .push(Box::new(message))
+            .await
+            .map_err(|(error, letter)| SendError::from_letter(error, letter))
+    }
+
+    /// Queues `message` if there's room for it right now.
+    pub fn try_send<M: Handled<T> + Send + Sync + 'static>(
+        &self,
+        message: M,
+    ) -> Result<(), SendError<M>> {
+        self.0
+            .try_push(Box::new(message))
+            .map_err(|(error, letter)| SendError::from_letter(error, letter))
+    }
+
+    pub async fn forward<M: Handled<T> + Send + Sync + 'static>(
+        &self,
+        message: Box<M>,
+    ) -> Result<(), SendError<Box<M>>> {
+        self.0.push(message).await.map_err(|(error, letter)| {
+            // The letter holds `M` itself, the box it came in is the mailbox's
+            SendError::<M>::from_letter(error, letter).map(Box::new)
+        })
+    }
+
+    pub async fn ask<M: Request + 'static>(&self, message: M) -> Result<M::Result, MailboxError>
+    where
+        Ask<M>: Handled<T>,
+    {
+        let (reply, response) = oneshot::channel();
+        self.0
+            .push(Box::new(Ask::new(message, reply)))
+            .await
+            .map_err(|(error, _)| error)?;
+
+        response.await.map_err(|_| MailboxError::Canceled)
+    }
+
+    /// Whether the actor is still accepting messages.
+    pub fn is_connected(&self) -> bool {
+        self.0.is_open()
+    }
+
+    /// Number of messages waiting to be handled.
+    pub fn queued(&self) -> usize {
+        self.0.len()
     }
 
-    pub fn forward<M: Handled<T> + Send + Sync + 'static>(&self, message: Box<M>) {
-        self.0.send(message).ok();
+    pub fn mailbox(&self) -> Mailbox {
+        self.0.config()
     }
 }
 
+/// What to do with messages still queued when an actor is stopped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopMode {
+    /// Handle every message already in the mailbox before stopping.
+    Drain,
+    /// Drop queued messages and stop right after the current one.
+    Discard,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Signal {
+    Stop(StopMode),
+    Restart,
+}
+
+type Control = mpsc::UnboundedSender<Signal>;
+type Signals = mpsc::UnboundedReceiver<Signal>;
+
 pub struct Runtime<T> {
     addr: Address<T>,
+    control: Control,
+    running: watch::Receiver<()>,
 }
 
 impl<T> Clone for Runtime<T> {
     fn clone(&self) -> Runtime<T> {
-        let subject = self.addr.0.clone();
-
         Runtime::<T> {
-            addr: Address(subject),
+            addr: self.addr.clone(),
+            control: self.control.clone(),
+            running: self.running.clone(),
         }
     }
 }
 
-impl<T: Default + Send + 'static> Deref for Runtime<T> {
+impl<T: Actor> Deref for Runtime<T> {
     type Target = Address<T>;
 
     fn deref(&self) -> &Address<T> {
@@ -65,34 +365,357 @@ impl<T: Default + Send + 'static> Deref for Runtime<T> {
     }
 }
 
-impl<T: Default + Send + 'static> Runtime<T> {
-    fn run() -> Self {
-        let (subject, stream) = mpsc::unbounded_channel::<Box<dyn Handled<T> + Send>>();
-        let handle = Handle(stream);
+impl<T: Actor> Runtime<T> {
+    /// Starts `actor` with its state as given.
+    pub fn spawn(actor: T) -> Self {
+        Self::spawn_with(actor, T::mailbox())
+    }
 
-        dispatch(handle);
+    /// Like `spawn`, queueing messages in `mailbox` instead of `Actor::mailbox()`.
+    pub fn spawn_with(actor: T, mailbox: Mailbox) -> Self {
+        Self::spawn_async_with(future::ready(actor), mailbox)
+    }
+
+    /// Starts the actor `init` resolves to, e.g. once its connections are open.
+    ///
+    /// Messages sent in the meantime are queued and handled after `started`.
+    pub fn spawn_async<F: Future<Output = T> + Send + 'static>(init: F) -> Self {
+        Self::spawn_async_with(init, T::mailbox())
+    }
+
+    /// Like `spawn_async`, queueing messages in `mailbox` instead of `Actor::mailbox()`.
+    pub fn spawn_async_with<F: Future<Output = T> + Send + 'static>(
+        init: F,
+        mailbox: Mailbox,
+    ) -> Self {
+        let (control, signals) = mpsc::unbounded_channel();
+
+        Self::launch(init, mailbox, control, signals, None)
+    }
+
+    fn launch<F: Future<Output = T> + Send + 'static>(
+        init: F,
+        mailbox: Mailbox,
+        control: Control,
+        signals: Signals,
+        supervision: Option<supervisor::Supervision<T>>,
+    ) -> Self {
+        let (subject, stream) = mailbox.open();
+        let (alive, running) = watch::channel(());
+
+        dispatch(init, Handle(stream), signals, alive, supervision);
 
         Self {
             addr: Address(subject),
+            control,
+            running,
+        }
+    }
+
+    /// Asks the actor to stop, rejecting new messages from this point on.
+    ///
+    /// With `StopMode::Drain` every message already queued is handled first. With
+    /// `StopMode::Discard` they're dropped once the message being handled, if any, is done.
+    pub fn stop(&self, mode: StopMode) {
+        // The actor is already gone if this fails
+        self.control.send(Signal::Stop(mode)).ok();
+        self.addr.0.close();
+    }
+
+    /// Resolves once the actor has stopped and been dropped.
+    pub fn join(&self) -> impl Future<Output = ()> {
+        let mut running = self.running.clone();
+
+        async move {
+            // `recv` returns `None` once the dispatch task drops its sender
+            while running.recv().await.is_some() {}
         }
     }
 }
 
-fn dispatch<T: Default + Send + 'static>(mut handle: Handle<T>) {
+enum Exit {
+    Closed,
+    Stop(StopMode),
+    Restart,
+    Panicked,
+}
+
+fn dispatch<T: Actor, F: Future<Output = T> + Send + 'static>(
+    init: F,
+    mut handle: Handle<T>,
+    mut signals: Signals,
+    alive: watch::Sender<()>,
+    supervision: Option<supervisor::Supervision<T>>,
+) {
     tokio::spawn(async move {
-        let mut dispatched = T::default();
-        while let Some(mut message) = handle.0.recv().await {
-            message.be_handled(&mut dispatched);
+        let mut dispatched = init.await;
+
+        loop {
+            dispatched.started();
+
+            let stop = match handle_messages(&mut dispatched, &mut handle, &mut signals).await {
+                Exit::Closed => None,
+                Exit::Stop(mode) => Some(mode),
+                Exit::Restart => {
+                    dispatched.stopping();
+                    dispatched.stopped();
+
+                    if let Some(supervision) = &supervision {
+                        dispatched = supervision.restart();
+                    }
+                    continue;
+                }
+                Exit::Panicked => match &supervision {
+                    Some(supervision) if supervision.should_restart().await => {
+                        // A panicked actor's state can't be trusted, so its hooks are skipped
+                        dispatched = supervision.restart();
+                        continue;
+                    }
+                    // Dropping the mailbox fails every pending and future send
+                    _ => return,
+                },
+            };
+
+            dispatched.stopping();
+            if let Some(mode) = stop {
+                handle.0.close();
+
+                if mode == StopMode::Drain {
+                    while let Some(message) = handle.0.recv().await {
+                        if handle_message(&mut dispatched, message).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+            dispatched.stopped();
+
+            break;
         }
+
+        drop(dispatched);
+        drop(alive);
     });
 }
 
+async fn handle_messages<T: Actor>(
+    actor: &mut T,
+    handle: &mut Handle<T>,
+    signals: &mut Signals,
+) -> Exit {
+    loop {
+        // A signal sent while the last message was handled goes before the next one
+        if let Ok(signal) = signals.try_recv() {
+            return signal.into();
+        }
+
+        tokio::select! {
+            message = handle.0.recv() => match message {
+                Some(message) => {
+                    if handle_message(actor, message).await.is_err() {
+                        return Exit::Panicked;
+                    }
+                }
+                None => return Exit::Closed,
+            },
+            Some(signal) = signals.recv() => return signal.into(),
+        }
+    }
+}
+
+impl From<Signal> for Exit {
+    fn from(signal: Signal) -> Self {
+        match signal {
+            Signal::Stop(mode) => Exit::Stop(mode),
+            Signal::Restart => Exit::Restart,
+        }
+    }
+}
+
+/// Handles a single message, catching a panicking handler instead of losing the task.
+async fn handle_message<T: Actor>(
+    actor: &mut T,
+    mut message: Box<dyn Handled<T> + Send>,
+) -> Result<(), Box<dyn Any + Send>> {
+    AssertUnwindSafe(async move { message.be_handled(actor).await })
+        .catch_unwind()
+        .await
+}
+
 pub trait SelfStarter: Sized {
     fn start() -> Runtime<Self>;
 }
 
-impl<T: Default + Send + 'static> SelfStarter for T {
+impl<T: Actor + Default> SelfStarter for T {
     fn start() -> Runtime<T> {
-        Runtime::run()
+        Runtime::spawn(T::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+    use crate::testing::{Add, Count, Counter};
+
+    struct SlowAdd(u32);
+    impl Message for SlowAdd {}
+
+    impl AsyncHandler<SlowAdd> for Counter {
+        fn handle_async<'a>(&'a mut self, message: &'a mut SlowAdd) -> BoxFuture<'a, ()> {
+            Box::pin(async move {
+                tokio::time::delay_for(std::time::Duration::from_millis(10)).await;
+                self.count += message.0;
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn async_handlers_finish_before_next_message() {
+        let runtime = Counter::start();
+
+        runtime.send(SlowAdd(2)).await.unwrap();
+        runtime.send(Add(3)).await.unwrap();
+
+        assert_eq!(runtime.ask(Count).await.unwrap(), 5);
+    }
+
+    #[tokio::test]
+    async fn undelivered_messages_are_handed_back() {
+        let runtime = Counter::start();
+        assert!(runtime.is_connected());
+
+        runtime.stop(StopMode::Discard);
+        runtime.join().await;
+        assert!(!runtime.is_connected());
+
+        match runtime.send(Add(3)).await {
+            Err(SendError::Closed(Add(value))) => assert_eq!(value, 3),
+            other => panic!("Expected the message back, got {:?}", other),
+        }
+        assert_eq!(runtime.try_send(Add(4)).unwrap_err().into_inner().0, 4);
+    }
+
+    struct Cramped;
+
+    impl Actor for Cramped {
+        fn mailbox() -> Mailbox {
+            Mailbox::bounded(1, Overflow::Fail)
+        }
+    }
+
+    impl Handler<Add> for Cramped {
+        fn handle(&mut self, _: &mut Add) {}
+    }
+
+    #[tokio::test]
+    async fn undelivered_forwards_are_handed_back() {
+        let runtime = Counter::start();
+        runtime.stop(StopMode::Discard);
+        runtime.join().await;
+
+        match runtime.forward(Box::new(Add(3))).await {
+            Err(SendError::Closed(message)) => assert_eq!(message.0, 3),
+            other => panic!("Expected the message back, got {:?}", other),
+        }
+
+        // Never started, so nothing is taken off its mailbox
+        let runtime = Runtime::spawn_async(future::pending::<Cramped>());
+        runtime.send(Add(1)).await.unwrap();
+
+        match runtime.forward(Box::new(Add(2))).await {
+            Err(SendError::Full(message)) => assert_eq!(message.0, 2),
+            other => panic!("Expected the message back, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn spawns_with_a_given_mailbox() {
+        let runtime = Runtime::spawn_with(Counter::default(), Mailbox::bounded(2, Overflow::Fail));
+        assert_eq!(runtime.mailbox(), Mailbox::bounded(2, Overflow::Fail));
+        runtime.send(Add(1)).await.unwrap();
+
+        assert_eq!(runtime.ask(Count).await.unwrap(), 1);
+
+        let mailbox = Mailbox::bounded(1, Overflow::Fail);
+        let runtime = Runtime::spawn_async_with(future::pending::<Counter>(), mailbox);
+        runtime.send(Add(1)).await.unwrap();
+
+        match runtime.try_send(Add(2)) {
+            Err(SendError::Full(Add(value))) => assert_eq!(value, 2),
+            other => panic!("Expected the message back, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_messages_right_after_stop() {
+        let runtime = Counter::start();
+        runtime.send(Add(1)).await.unwrap();
+
+        runtime.stop(StopMode::Drain);
+        assert!(!runtime.is_connected());
+
+        match runtime.try_send(Add(2)) {
+            Err(SendError::Closed(Add(value))) => assert_eq!(value, 2),
+            other => panic!("Expected the message back, got {:?}", other),
+        }
+        assert!(runtime.send(Add(3)).await.unwrap_err().is_closed());
+
+        runtime.join().await;
+    }
+
+    #[tokio::test]
+    async fn spawns_from_explicit_state() {
+        let runtime = Runtime::spawn(Counter::new(4));
+        runtime.send(Add(1)).await.unwrap();
+
+        assert_eq!(runtime.ask(Count).await.unwrap(), 5);
+
+        let runtime = Runtime::spawn_async(async { Counter::new(7) });
+
+        assert_eq!(runtime.ask(Count).await.unwrap(), 7);
+    }
+
+    #[tokio::test]
+    async fn ask_resolves_with_reply() {
+        let runtime = Counter::start();
+
+        runtime.send(Add(2)).await.unwrap();
+        runtime.send(Add(3)).await.unwrap();
+
+        assert_eq!(runtime.ask(Count).await.unwrap(), 5);
+    }
+
+    static TALLIED: AtomicU32 = AtomicU32::new(0);
+    static STOPPED: AtomicBool = AtomicBool::new(false);
+
+    #[derive(Default)]
+    struct Tally;
+
+    impl Actor for Tally {
+        fn stopped(&mut self) {
+            STOPPED.store(true, Ordering::SeqCst);
+        }
+    }
+
+    impl Handler<Add> for Tally {
+        fn handle(&mut self, message: &mut Add) {
+            TALLIED.fetch_add(message.0, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn drained_stop_handles_queued_messages() {
+        let runtime = Tally::start();
+
+        runtime.send(Add(1)).await.unwrap();
+        runtime.send(Add(2)).await.unwrap();
+        runtime.stop(StopMode::Drain);
+        runtime.join().await;
+
+        assert_eq!(TALLIED.load(Ordering::SeqCst), 3);
+        assert!(STOPPED.load(Ordering::SeqCst));
     }
 }
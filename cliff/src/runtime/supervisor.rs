@@ -0,0 +1,363 @@
+use std::{
+    collections::VecDeque,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use futures::future;
+
+use tokio::sync::mpsc;
+
+use super::{
+    Actor, Address, Control, Handled, Handler, MailboxError, Message, Request, Responder, Runtime,
+    Signal, StopMode,
+};
+
+static CHILD_IDS: AtomicUsize = AtomicUsize::new(0);
+
+/// How a supervisor reacts to one of its children panicking.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    /// Only the failed child is restarted.
+    OneForOne,
+    /// Every child is restarted along with the failed one.
+    OneForAll,
+}
+
+/// Sent to a supervisor's parent whenever it handles a child's failure.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SupervisionEvent {
+    /// The named child panicked and was restarted.
+    Restarted(String),
+    /// The named child panicked once too often, so every child was stopped.
+    GaveUp(String),
+}
+impl Message for SupervisionEvent {}
+
+type Notifier = Box<dyn Fn(SupervisionEvent) + Send + Sync>;
+
+struct Child {
+    id: usize,
+    name: String,
+    control: Control,
+}
+
+/// Restarts panicking actors with a fresh state.
+///
+/// Restarted actors keep their mailbox, so every `Address` handed out stays valid.
+/// More than `max_restarts` failures in a `within` window make the supervisor give up.
+///
+/// ```ignore
+/// let supervisor = Runtime::spawn(Supervisor::new(Strategy::OneForOne));
+/// let projects = supervisor.supervise("projects", Projects::default)?;
+/// ```
+pub struct Supervisor {
+    strategy: Strategy,
+    max_restarts: usize,
+    within: Duration,
+    restarts: VecDeque<Instant>,
+    children: Vec<Child>,
+    parent: Option<Notifier>,
+}
+
+impl Supervisor {
+    pub fn new(strategy: Strategy) -> Self {
+        Self {
+            strategy,
+            max_restarts: 3,
+            within: Duration::from_secs(5),
+            restarts: VecDeque::new(),
+            children: vec![],
+            parent: None,
+        }
+    }
+
+    pub fn with_intensity(mut self, max_restarts: usize, within: Duration) -> Self {
+        self.max_restarts = max_restarts;
+        self.within = within;
+
+        self
+    }
+
+    /// Reports every `SupervisionEvent` to `parent`.
+    pub fn with_parent<P: Actor>(mut self, parent: Address<P>) -> Self
+    where
+        SupervisionEvent: Handled<P>,
+    {
+        self.parent = Some(Box::new(move |event| {
+            // Only wait on the parent's mailbox when it's full, keeping events in order otherwise
+            if let Err((MailboxError::Full, event)) = parent.0.try_push(Box::new(event)) {
+                let parent = parent.clone();
+
+                tokio::spawn(async move {
+                    parent.0.push(event).await.ok();
+                });
+            }
+        }));
+
+        self
+    }
+
+    fn notify(&self, event: SupervisionEvent) {
+        if let Some(parent) = &self.parent {
+            parent(event);
+        }
+    }
+
+    fn exceeded_intensity(&mut self) -> bool {
+        let now = Instant::now();
+        self.restarts.push_back(now);
+
+        while let Some(oldest) = self.restarts.front() {
+            if now.duration_since(*oldest) <= self.within {
+                break;
+            }
+            self.restarts.pop_front();
+        }
+
+        self.restarts.len() > self.max_restarts
+    }
+}
+
+impl Actor for Supervisor {
+    fn stopping(&mut self) {
+        for child in &self.children {
+            child.control.send(Signal::Stop(StopMode::Drain)).ok();
+        }
+    }
+}
+
+impl Runtime<Supervisor> {
+    /// Starts the actor built by `factory` under this supervisor.
+    ///
+    /// `factory` is called again for every restart. Fails without starting the
+    /// actor if the supervisor can't take it on, as it stopped or its mailbox is full.
+    pub fn supervise<T: Actor, F: Fn() -> T + Send + Sync + 'static>(
+        &self,
+        name: &str,
+        factory: F,
+    ) -> Result<Runtime<T>, MailboxError> {
+        let id = CHILD_IDS.fetch_add(1, Ordering::Relaxed);
+        let (control, signals) = mpsc::unbounded_channel();
+
+        // Registered before the child starts, so its first failure always finds it
+        self.addr
+            .0
+            .try_push(Box::new(Watch(Some(Child {
+                id,
+                name: name.to_string(),
+                control: control.clone(),
+            }))))
+            .map_err(|(error, _)| error)?;
+
+        let actor = factory();
+        let supervision = Supervision {
+            id,
+            factory: Box::new(factory),
+            supervisor: self.addr.clone(),
+        };
+
+        Ok(Runtime::launch(
+            future::ready(actor),
+            T::mailbox(),
+            control,
+            signals,
+            Some(supervision),
+        ))
+    }
+}
+
+pub(super) struct Supervision<T> {
+    id: usize,
+    factory: Box<dyn Fn() -> T + Send + Sync>,
+    supervisor: Address<Supervisor>,
+}
+
+impl<T> Supervision<T> {
+    pub(super) async fn should_restart(&self) -> bool {
+        // A supervisor that's gone can't restart anything
+        self.supervisor.ask(Failed(self.id)).await.unwrap_or(false)
+    }
+
+    pub(super) fn restart(&self) -> T {
+        (self.factory)()
+    }
+}
+
+struct Watch(Option<Child>);
+impl Message for Watch {}
+
+impl Handler<Watch> for Supervisor {
+    fn handle(&mut self, message: &mut Watch) {
+        if let Some(child) = message.0.take() {
+            self.children.push(child);
+        }
+    }
+}
+
+struct Failed(usize);
+impl Message for Failed {}
+impl Request for Failed {
+    type Result = bool;
+}
+
+impl Responder<Failed> for Supervisor {
+    fn respond(&mut self, message: &mut Failed) -> bool {
+        let failed = message.0;
+        let name = self
+            .children
+            .iter()
+            .find(|child| child.id == failed)
+            .map(|child| child.name.clone())
+            .unwrap_or_default();
+
+        if self.exceeded_intensity() {
+            for child in self.children.drain(..).filter(|child| child.id != failed) {
+                child.control.send(Signal::Stop(StopMode::Discard)).ok();
+            }
+            self.notify(SupervisionEvent::GaveUp(name));
+
+            return false;
+        }
+
+        if self.strategy == Strategy::OneForAll {
+            // Children that already stopped are forgotten along the way
+            self.children
+                .retain(|child| child.id == failed || child.control.send(Signal::Restart).is_ok());
+        }
+        self.notify(SupervisionEvent::Restarted(name));
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+
+    use crate::runtime::StopMode;
+
+    #[derive(Default)]
+    struct Fragile {
+        handled: u32,
+    }
+    impl Actor for Fragile {}
+
+    struct Poke;
+    impl Message for Poke {}
+
+    impl Handler<Poke> for Fragile {
+        fn handle(&mut self, _: &mut Poke) {
+            self.handled += 1;
+        }
+    }
+
+    struct Break;
+    impl Message for Break {}
+
+    impl Handler<Break> for Fragile {
+        fn handle(&mut self, _: &mut Break) {
+            panic!("Broken on purpose");
+        }
+    }
+
+    struct Pokes;
+    impl Message for Pokes {}
+    impl Request for Pokes {
+        type Result = u32;
+    }
+
+    impl Responder<Pokes> for Fragile {
+        fn respond(&mut self, _: &mut Pokes) -> u32 {
+            self.handled
+        }
+    }
+
+    #[derive(Default)]
+    struct Parent(Arc<Mutex<Vec<SupervisionEvent>>>);
+    impl Actor for Parent {}
+
+    impl Handler<SupervisionEvent> for Parent {
+        fn handle(&mut self, message: &mut SupervisionEvent) {
+            self.0.lock().unwrap().push(message.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn one_for_one_restarts_the_failed_child() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let parent = Runtime::spawn(Parent(events.clone()));
+        let supervisor =
+            Runtime::spawn(Supervisor::new(Strategy::OneForOne).with_parent((*parent).clone()));
+
+        let failing = supervisor.supervise("failing", Fragile::default).unwrap();
+        let sibling = supervisor.supervise("sibling", Fragile::default).unwrap();
+
+        failing.send(Poke).await.unwrap();
+        sibling.send(Poke).await.unwrap();
+        failing.send(Break).await.unwrap();
+        failing.send(Poke).await.unwrap();
+
+        assert_eq!(failing.ask(Pokes).await.unwrap(), 1);
+        assert_eq!(sibling.ask(Pokes).await.unwrap(), 1);
+
+        parent.stop(StopMode::Drain);
+        parent.join().await;
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![SupervisionEvent::Restarted("failing".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn one_for_all_restarts_every_child() {
+        let supervisor = Runtime::spawn(Supervisor::new(Strategy::OneForAll));
+        let (built, mut builds) = mpsc::unbounded_channel();
+
+        let failing = supervisor.supervise("failing", Fragile::default).unwrap();
+        let sibling = supervisor
+            .supervise("sibling", move || {
+                built.send(()).ok();
+                Fragile::default()
+            })
+            .unwrap();
+        builds.recv().await.unwrap();
+
+        sibling.send(Poke).await.unwrap();
+        assert_eq!(sibling.ask(Pokes).await.unwrap(), 1);
+
+        failing.send(Break).await.unwrap();
+        assert_eq!(failing.ask(Pokes).await.unwrap(), 0);
+
+        // The restart signal reaches the sibling out of band, wait until it's rebuilt
+        builds.recv().await.unwrap();
+        assert_eq!(sibling.ask(Pokes).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn gives_up_past_max_intensity() {
+        let supervisor = Runtime::spawn(
+            Supervisor::new(Strategy::OneForOne).with_intensity(1, Duration::from_secs(60)),
+        );
+
+        let failing = supervisor.supervise("failing", Fragile::default).unwrap();
+
+        failing.send(Break).await.unwrap();
+        failing.send(Break).await.unwrap();
+        failing.join().await;
+
+        assert!(failing.send(Poke).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn stopped_supervisors_take_no_children() {
+        let supervisor = Runtime::spawn(Supervisor::new(Strategy::OneForOne));
+        supervisor.stop(StopMode::Discard);
+        supervisor.join().await;
+
+        let refused = supervisor.supervise("orphan", Fragile::default);
+        assert_eq!(refused.err(), Some(MailboxError::Closed));
+    }
+}
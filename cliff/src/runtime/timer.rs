@@ -0,0 +1,321 @@
+use std::{
+    future::Future,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use futures::future::{self, AbortHandle, Abortable, BoxFuture, FutureExt};
+
+use tokio::{
+    sync::oneshot,
+    time::{self, Instant},
+};
+
+use super::{Address, Handled};
+
+/// Cancels a delayed or periodic delivery.
+///
+/// Dropping the handle leaves the timer running.
+#[derive(Clone)]
+pub struct TimerHandle(AbortHandle);
+
+impl TimerHandle {
+    pub fn cancel(&self) {
+        self.0.abort();
+    }
+}
+
+/// The time source timers are scheduled against.
+///
+/// `Clock::system()` follows tokio's clock. `Clock::manual()` stands still until `advance`d,
+/// which lets tests step through time deterministically:
+///
+/// ```ignore
+/// let clock = Clock::manual();
+/// clock.send_after(&runtime, Duration::from_secs(60), Add(1));
+/// clock.advance(Duration::from_secs(60)).await;
+/// ```
+#[derive(Clone)]
+pub struct Clock(Option<Arc<Mutex<Manual>>>);
+
+struct Manual {
+    now: Instant,
+    sleepers: Vec<Sleeper>,
+}
+
+struct Sleeper {
+    deadline: Instant,
+    wake: oneshot::Sender<Fired>,
+}
+
+/// Held by a timer while it delivers its message, so `advance` can wait on it.
+struct Fired {
+    _delivering: Option<oneshot::Sender<()>>,
+}
+
+impl Clock {
+    pub fn system() -> Self {
+        Self(None)
+    }
+
+    pub fn manual() -> Self {
+        Self(Some(Arc::new(Mutex::new(Manual {
+            now: Instant::now(),
+            sleepers: vec![],
+        }))))
+    }
+
+    pub fn now(&self) -> Instant {
+        match &self.0 {
+            Some(manual) => lock(manual).now,
+            None => Instant::now(),
+        }
+    }
+
+    /// Moves a manual clock forward, firing every timer that falls due along the way.
+    ///
+    /// Resolves once the fired timers have delivered their messages.
+    pub async fn advance(&self, duration: Duration) {
+        let manual = self
+            .0
+            .as_ref()
+            .expect("Only a manual clock can be advanced");
+        let target = lock(manual).now + duration;
+
+        loop {
+            let sleeper = {
+                let mut manual = lock(manual);
+                let due = manual
+                    .sleepers
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, sleeper)| sleeper.deadline <= target)
+                    .min_by_key(|(_, sleeper)| sleeper.deadline)
+                    .map(|(i, _)| i);
+
+                match due {
+                    Some(i) => {
+                        let sleeper = manual.sleepers.remove(i);
+                        manual.now = sleeper.deadline;
+
+                        sleeper
+                    }
+                    None => {
+                        manual.now = target;
+                        break;
+                    }
+                }
+            };
+
+            let (delivering, delivered) = oneshot::channel();
+            let fired = Fired {
+                _delivering: Some(delivering),
+            };
+            // Canceled timers are no longer listening
+            if sleeper.wake.send(fired).is_ok() {
+                delivered.await.ok();
+            }
+        }
+    }
+
+    /// Delivers `message` to `address` once `delay` has passed.
+    pub fn send_after<T, M>(&self, address: &Address<T>, delay: Duration, message: M) -> TimerHandle
+    where
+        T: 'static,
+        M: Handled<T> + Send + Sync + 'static,
+    {
+        let mailbox = address.0.downgrade();
+        let sleep = self.sleep_until(self.now() + delay);
+
+        schedule(async move {
+            let _fired = sleep.await;
+
+            if let Some(sender) = mailbox.upgrade() {
+                Address(sender).send(message).await.ok();
+            }
+        })
+    }
+
+    /// Delivers a message built by `factory` to `address` every `period`,
+    /// starting one `period` from now.
+    ///
+    /// Stops by itself once the actor is gone.
+    pub fn run_interval<T, M, F>(
+        &self,
+        address: &Address<T>,
+        period: Duration,
+        mut factory: F,
+    ) -> TimerHandle
+    where
+        T: 'static,
+        M: Handled<T> + Send + Sync + 'static,
+        F: FnMut() -> M + Send + 'static,
+    {
+        let mailbox = address.0.downgrade();
+        let clock = self.clone();
+        let mut deadline = self.now() + period;
+        let mut sleep = self.sleep_until(deadline);
+
+        schedule(async move {
+            loop {
+                let fired = sleep.await;
+
+                let address = match mailbox.upgrade() {
+                    Some(sender) => Address(sender),
+                    None => break,
+                };
+                if address.send(factory()).await.is_err() {
+                    break;
+                }
+
+                deadline += period;
+                // Registered before letting go of this tick, so a manual clock fires it too
+                sleep = clock.sleep_until(deadline);
+                drop(fired);
+            }
+        })
+    }
+
+    // Registers with a manual clock right away rather than on first poll
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, Fired> {
+        let manual = match &self.0 {
+            Some(manual) => manual,
+            None => {
+                return time::delay_until(deadline)
+                    .map(|_| Fired { _delivering: None })
+                    .boxed()
+            }
+        };
+
+        let mut manual = lock(manual);
+        if deadline <= manual.now {
+            return future::ready(Fired { _delivering: None }).boxed();
+        }
+
+        let (wake, woken) = oneshot::channel();
+        manual.sleepers.push(Sleeper { deadline, wake });
+
+        async move {
+            match woken.await {
+                Ok(fired) => fired,
+                // The clock is gone, so this deadline never comes
+                Err(_) => future::pending().await,
+            }
+        }
+        .boxed()
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::system()
+    }
+}
+
+fn lock(manual: &Mutex<Manual>) -> MutexGuard<'_, Manual> {
+    manual.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Timers only hold a weak reference to the mailbox, so they never keep an actor alive.
+impl<T: 'static> Address<T> {
+    /// Delivers `message` once `delay` has passed on the system clock.
+    pub fn send_after<M: Handled<T> + Send + Sync + 'static>(
+        &self,
+        delay: Duration,
+        message: M,
+    ) -> TimerHandle {
+        Clock::system().send_after(self, delay, message)
+    }
+
+    /// Delivers a message built by `factory` every `period` on the system clock.
+    pub fn run_interval<M, F>(&self, period: Duration, factory: F) -> TimerHandle
+    where
+        M: Handled<T> + Send + Sync + 'static,
+        F: FnMut() -> M + Send + 'static,
+    {
+        Clock::system().run_interval(self, period, factory)
+    }
+}
+
+fn schedule<F: Future<Output = ()> + Send + 'static>(timer: F) -> TimerHandle {
+    let (handle, registration) = AbortHandle::new_pair();
+
+    tokio::spawn(Abortable::new(timer, registration));
+
+    TimerHandle(handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        runtime::{Actor, Handler, Runtime},
+        testing::{Add, Count, Counter},
+    };
+
+    #[tokio::test]
+    async fn delivers_after_delay() {
+        let clock = Clock::manual();
+        let runtime = Runtime::spawn(Counter::default());
+
+        clock.send_after(&runtime, Duration::from_secs(10), Add(1));
+
+        clock.advance(Duration::from_secs(9)).await;
+        assert_eq!(runtime.ask(Count).await.unwrap(), 0);
+
+        clock.advance(Duration::from_secs(1)).await;
+        assert_eq!(runtime.ask(Count).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn runs_intervals_until_canceled() {
+        let clock = Clock::manual();
+        let runtime = Runtime::spawn(Counter::default());
+
+        let timer = clock.run_interval(&runtime, Duration::from_secs(1), || Add(1));
+
+        clock.advance(Duration::from_secs(3)).await;
+        assert_eq!(runtime.ask(Count).await.unwrap(), 3);
+
+        timer.cancel();
+        clock.advance(Duration::from_secs(5)).await;
+        assert_eq!(runtime.ask(Count).await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn system_clock_delivers() {
+        let runtime = Runtime::spawn(Counter::default());
+
+        runtime.send_after(Duration::from_millis(10), Add(1));
+
+        time::delay_for(Duration::from_millis(50)).await;
+        assert_eq!(runtime.ask(Count).await.unwrap(), 1);
+    }
+
+    struct Watched(Option<oneshot::Sender<()>>);
+    impl Actor for Watched {
+        fn stopped(&mut self) {
+            if let Some(stopped) = self.0.take() {
+                stopped.send(()).ok();
+            }
+        }
+    }
+
+    impl Handler<Add> for Watched {
+        fn handle(&mut self, _: &mut Add) {}
+    }
+
+    #[tokio::test]
+    async fn timers_dont_keep_actors_alive() {
+        let clock = Clock::manual();
+        let (stopped, has_stopped) = oneshot::channel();
+        let runtime = Runtime::spawn(Watched(Some(stopped)));
+
+        let _timer = clock.run_interval(&runtime, Duration::from_secs(1), || Add(1));
+        drop(runtime);
+
+        let stopped = time::timeout(Duration::from_secs(1), has_stopped).await;
+        assert!(stopped.is_ok());
+    }
+}
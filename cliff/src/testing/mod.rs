@@ -0,0 +1,146 @@
+//! Fixtures shared by the crate's tests.
+
+use std::{
+    env, fs,
+    marker::PhantomData,
+    ops::Deref,
+    path::{Path, PathBuf},
+    process,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    codec::{Registered, Registry},
+    runtime::{Actor, Handler, Message, Request, Responder},
+    ConnectionId,
+};
+
+/// Adds up every `Add` it's sent, answering `Count` with the total.
+#[derive(Default)]
+pub(crate) struct Counter {
+    pub(crate) count: u32,
+    /// Connections it was told were lost, for the server tests.
+    pub(crate) lost: Vec<ConnectionId>,
+}
+impl Actor for Counter {}
+
+impl Counter {
+    pub(crate) fn new(count: u32) -> Self {
+        Self {
+            count,
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub(crate) struct Add(pub(crate) u32);
+impl Message for Add {}
+
+impl Handler<Add> for Counter {
+    fn handle(&mut self, message: &mut Add) {
+        self.count += message.0;
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Count;
+impl Message for Count {}
+impl Request for Count {
+    type Result = u32;
+}
+
+impl Responder<Count> for Counter {
+    fn respond(&mut self, _: &mut Count) -> u32 {
+        self.count
+    }
+}
+
+impl Registered for Counter {
+    fn registry() -> Registry<Self> {
+        let mut registry = Registry::new();
+        registry.register::<Add>("Add");
+        registry.register_request::<Count>("Count");
+
+        registry
+    }
+}
+
+/// Keeps a copy of every `M` it's sent, handing them back on `Recorded`.
+pub(crate) struct Recorder<M>(Vec<M>);
+
+impl<M> Default for Recorder<M> {
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl<M: Send + 'static> Actor for Recorder<M> {}
+
+impl<M: Message + Clone + 'static> Handler<M> for Recorder<M> {
+    fn handle(&mut self, message: &mut M) {
+        self.0.push(message.clone());
+    }
+}
+
+pub(crate) struct Recorded<M>(PhantomData<fn() -> M>);
+
+impl<M> Recorded<M> {
+    pub(crate) fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<M: Send + 'static> Message for Recorded<M> {}
+impl<M: Send + 'static> Request for Recorded<M> {
+    type Result = Vec<M>;
+}
+
+impl<M: Clone + Send + 'static> Responder<Recorded<M>> for Recorder<M> {
+    fn respond(&mut self, _: &mut Recorded<M>) -> Vec<M> {
+        self.0.clone()
+    }
+}
+
+/// A path in the temp dir that's unique to the test process, removed when dropped.
+///
+/// Whatever was left there by an earlier run is removed up front.
+pub(crate) struct TempPath(PathBuf);
+
+impl TempPath {
+    pub(crate) fn new(name: &str) -> Self {
+        let path = Self(env::temp_dir().join(format!("cliff-{}-{}", process::id(), name)));
+        path.remove();
+
+        path
+    }
+
+    fn remove(&self) {
+        if self.0.is_dir() {
+            fs::remove_dir_all(&self.0).ok();
+        } else {
+            fs::remove_file(&self.0).ok();
+        }
+    }
+}
+
+impl Deref for TempPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempPath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        self.remove();
+    }
+}
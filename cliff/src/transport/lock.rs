@@ -0,0 +1,119 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+};
+
+use failure::{Error, ResultExt};
+
+use super::{create_private_parent, AlreadyRunning, Endpoint};
+
+/// Keeps a second station from running against the same endpoint.
+///
+/// Holds an exclusive lock on a file that records the owner's pid, released
+/// once dropped or once the process dies.
+#[derive(Debug)]
+pub struct LockFile {
+    path: PathBuf,
+    _file: File,
+}
+
+impl LockFile {
+    pub fn acquire(path: impl AsRef<Path>, endpoint: &Endpoint) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|_| format!("Couldn't open {}", path.display()))?;
+
+        // Safe, the descriptor stays open for as long as `file` lives
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let error = io::Error::last_os_error();
+            if error.kind() != io::ErrorKind::WouldBlock {
+                return Err(Error::from(error));
+            }
+
+            let mut pid = String::new();
+            file.read_to_string(&mut pid).ok();
+
+            return Err(Error::from(AlreadyRunning {
+                endpoint: endpoint.clone(),
+                pid: pid.trim().parse().ok(),
+            }));
+        }
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        write!(file, "{}", std::process::id())?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            _file: file,
+        })
+    }
+
+    /// Locks `endpoint` through a `.lock` file next to its socket.
+    ///
+    /// The socket's directory is created the way binding it would, if missing.
+    /// TCP endpoints have nothing to lock, their port can only be bound once already.
+    pub fn for_endpoint(endpoint: &Endpoint) -> Result<Option<Self>, Error> {
+        match endpoint {
+            Endpoint::Unix(socket) => {
+                create_private_parent(socket)?;
+
+                let mut path = socket.clone().into_os_string();
+                path.push(".lock");
+
+                Self::acquire(path, endpoint).map(Some)
+            }
+            Endpoint::Tcp(_) => Ok(None),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use crate::testing::TempPath;
+
+    #[test]
+    fn only_one_holder_at_a_time() {
+        let dir = TempPath::new("lock");
+        let endpoint = Endpoint::Unix(dir.join("central.sock"));
+
+        let lock = LockFile::for_endpoint(&endpoint).unwrap().unwrap();
+        let pid = fs::read_to_string(lock.path()).unwrap();
+        assert_eq!(pid, std::process::id().to_string());
+
+        let error = LockFile::for_endpoint(&endpoint).unwrap_err();
+        let running = error.downcast_ref::<AlreadyRunning>().unwrap();
+        assert_eq!(running.pid, Some(std::process::id()));
+
+        drop(lock);
+        assert!(LockFile::for_endpoint(&endpoint).unwrap().is_some());
+    }
+
+    #[test]
+    fn creates_the_socket_directory() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempPath::new("lock-fresh");
+        let endpoint = Endpoint::Unix(dir.join("central.sock"));
+
+        let lock = LockFile::for_endpoint(&endpoint).unwrap().unwrap();
+        assert!(lock.path().exists());
+        let mode = fs::metadata(&*dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+    }
+}
@@ -0,0 +1,353 @@
+mod lock;
+mod peer;
+mod resolve;
+
+pub use lock::LockFile;
+pub use peer::{Allowlist, Authorize, PeerCredentials, SameUser};
+pub use resolve::{CONFIG_VAR, ENDPOINT_VAR};
+
+use std::{
+    error, fmt,
+    fs::{self, DirBuilder},
+    io::{self, ErrorKind},
+    net::{self, SocketAddr},
+    os::unix::{fs::DirBuilderExt, net::UnixStream as StdUnixStream},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use failure::{Error, ResultExt};
+
+use crate::codec::{Gate, Secret};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream, UnixListener, UnixStream},
+};
+
+/// Where a server listens and clients connect.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Endpoint {
+    /// A Unix domain socket, for processes on the same machine.
+    Unix(PathBuf),
+    /// A TCP address, for everything else.
+    Tcp(SocketAddr),
+}
+
+impl Endpoint {
+    /// The endpoint modules and the station meet at, unless told otherwise.
+    pub fn local() -> Result<Self, Error> {
+        Self::resolve(None)
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Endpoint::Unix(path) => write!(f, "unix:{}", path.display()),
+            Endpoint::Tcp(addr) => write!(f, "tcp:{}", addr),
+        }
+    }
+}
+
+/// Something is already serving on an endpoint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlreadyRunning {
+    pub endpoint: Endpoint,
+    /// Known when it holds the endpoint's `LockFile`.
+    pub pid: Option<u32>,
+}
+
+impl fmt::Display for AlreadyRunning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Already running on {}", self.endpoint)?;
+        if let Some(pid) = self.pid {
+            write!(f, " (pid {})", pid)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl error::Error for AlreadyRunning {}
+
+pub type ReadHalf = Box<dyn AsyncRead + Send + Unpin>;
+pub type WriteHalf = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// A connected stream, whatever the transport underneath.
+pub struct Socket {
+    read: ReadHalf,
+    write: WriteHalf,
+    peer: Option<PeerCredentials>,
+}
+
+impl Socket {
+    pub async fn connect(endpoint: &Endpoint) -> io::Result<Self> {
+        match endpoint {
+            Endpoint::Unix(path) => UnixStream::connect(path).await.map(Socket::from),
+            Endpoint::Tcp(addr) => TcpStream::connect(addr).await.map(Socket::from),
+        }
+    }
+
+    /// Who's on the other end, for Unix sockets.
+    pub fn peer(&self) -> Option<PeerCredentials> {
+        self.peer
+    }
+
+    pub fn into_split(self) -> (ReadHalf, WriteHalf) {
+        (self.read, self.write)
+    }
+}
+
+impl From<UnixStream> for Socket {
+    fn from(stream: UnixStream) -> Self {
+        let peer = PeerCredentials::of(&stream).ok();
+        let (read, write) = stream.into_split();
+
+        Self {
+            read: Box::new(read),
+            write: Box::new(write),
+            peer,
+        }
+    }
+}
+
+impl From<TcpStream> for Socket {
+    fn from(stream: TcpStream) -> Self {
+        // Messages are small and often waited on, batching them only adds latency
+        stream.set_nodelay(true).ok();
+        let (read, write) = stream.into_split();
+
+        Self {
+            read: Box::new(read),
+            write: Box::new(write),
+            peer: None,
+        }
+    }
+}
+
+/// Accepts connections on an `Endpoint`.
+///
+/// Unix connections are only let in when their peer passes the listener's `Authorize`
+/// policy, `SameUser` unless told otherwise.
+///
+/// Clients also have to prove they know the listener's `Secret` during the handshake,
+/// when it has one. TCP listeners without one turn every client away.
+pub struct Listener {
+    bound: Bound,
+    policy: Arc<dyn Authorize>,
+    secret: Option<Secret>,
+}
+
+enum Bound {
+    Unix(UnixListener),
+    Tcp(TcpListener),
+}
+
+impl Listener {
+    pub fn bind(endpoint: &Endpoint) -> Result<Self, Error> {
+        let bound = match endpoint {
+            Endpoint::Unix(path) => Bound::Unix(bind_unix(path)?),
+            Endpoint::Tcp(addr) => {
+                let listener = net::TcpListener::bind(addr)
+                    .with_context(|_| format!("Couldn't bind to {}", addr))?;
+                listener.set_nonblocking(true)?;
+
+                Bound::Tcp(TcpListener::from_std(listener)?)
+            }
+        };
+
+        Ok(Self {
+            bound,
+            policy: Arc::new(SameUser),
+            secret: None,
+        })
+    }
+
+    pub fn with_policy<A: Authorize>(mut self, policy: A) -> Self {
+        self.policy = Arc::new(policy);
+
+        self
+    }
+
+    pub fn with_secret(mut self, secret: Secret) -> Self {
+        self.secret = Some(secret);
+
+        self
+    }
+
+    pub(crate) fn gate(&self) -> Gate {
+        let open = match self.bound {
+            Bound::Unix(_) => true,
+            Bound::Tcp(_) => false,
+        };
+
+        Gate::new(self.secret.clone(), open)
+    }
+
+    /// Where the listener ended up, with the actual port when bound to port 0.
+    pub fn local_endpoint(&self) -> io::Result<Endpoint> {
+        match &self.bound {
+            Bound::Unix(listener) => {
+                let addr = listener.local_addr()?;
+                let path = addr
+                    .as_pathname()
+                    .ok_or_else(|| io::Error::other("Unnamed Unix socket"))?;
+
+                Ok(Endpoint::Unix(path.to_path_buf()))
+            }
+            Bound::Tcp(listener) => listener.local_addr().map(Endpoint::Tcp),
+        }
+    }
+
+    /// Waits for the next connection the policy lets in, dropping the others.
+    ///
+    /// Errors are usually transient, e.g. running out of file descriptors, and
+    /// callers are expected to keep accepting after one.
+    pub async fn accept(&mut self) -> io::Result<Socket> {
+        loop {
+            let socket: Socket = match &mut self.bound {
+                Bound::Unix(listener) => listener.accept().await?.0.into(),
+                Bound::Tcp(listener) => return Ok(listener.accept().await?.0.into()),
+            };
+
+            match socket.peer() {
+                Some(peer) if self.policy.authorize(&peer) => return Ok(socket),
+                _ => continue,
+            }
+        }
+    }
+}
+
+/// Creates the directory `path` goes in if it's missing, readable by its owner only.
+fn create_private_parent(path: &Path) -> Result<(), Error> {
+    // Only the user running the station gets to reach it
+    if let Some(parent) = path.parent().filter(|parent| !parent.exists()) {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(parent)
+            .with_context(|_| format!("Couldn't create {}", parent.display()))?;
+    }
+
+    Ok(())
+}
+
+fn bind_unix(path: &PathBuf) -> Result<UnixListener, Error> {
+    create_private_parent(path)?;
+
+    match UnixListener::bind(path) {
+        Ok(l) => Ok(l),
+        Err(e) if e.kind() == ErrorKind::AddrInUse => {
+            // A socket file nobody answers on was left behind by a server that died
+            if StdUnixStream::connect(path).is_ok() {
+                return Err(Error::from(AlreadyRunning {
+                    endpoint: Endpoint::Unix(path.clone()),
+                    pid: None,
+                }));
+            }
+            fs::remove_file(path)?;
+
+            UnixListener::bind(path).map_err(Error::from)
+        }
+        Err(e) => Err(Error::from(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::testing::TempPath;
+
+    async fn echo_once(endpoint: Endpoint) {
+        let mut listener = Listener::bind(&endpoint).unwrap();
+        let endpoint = listener.local_endpoint().unwrap();
+
+        tokio::spawn(async move {
+            let (mut read, mut write) = listener.accept().await.unwrap().into_split();
+            let mut buffer = [0; 4];
+
+            read.read_exact(&mut buffer).await.unwrap();
+            write.write_all(&buffer).await.unwrap();
+        });
+
+        let (mut read, mut write) = Socket::connect(&endpoint).await.unwrap().into_split();
+        let mut buffer = [0; 4];
+
+        write.write_all(b"ping").await.unwrap();
+        read.read_exact(&mut buffer).await.unwrap();
+        assert_eq!(&buffer, b"ping");
+    }
+
+    #[tokio::test]
+    async fn connects_over_tcp() {
+        echo_once(Endpoint::Tcp("127.0.0.1:0".parse().unwrap())).await;
+    }
+
+    #[tokio::test]
+    async fn connects_over_unix_sockets() {
+        let path = TempPath::new("transport.sock");
+
+        echo_once(Endpoint::Unix(path.to_path_buf())).await;
+    }
+
+    #[tokio::test]
+    async fn replaces_stale_sockets_only() {
+        let path = TempPath::new("stale.sock");
+        let endpoint = Endpoint::Unix(path.to_path_buf());
+
+        // Dropping a listener leaves its socket file behind
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let listener = Listener::bind(&endpoint).unwrap();
+
+        let error = Listener::bind(&endpoint).err().unwrap();
+        assert_eq!(
+            error.downcast_ref::<AlreadyRunning>(),
+            Some(&AlreadyRunning {
+                endpoint,
+                pid: None
+            })
+        );
+
+        drop(listener);
+    }
+
+    #[tokio::test]
+    async fn rejects_unauthorized_peers() {
+        let path = TempPath::new("peers.sock");
+        let endpoint = Endpoint::Unix(path.to_path_buf());
+        let mut listener = Listener::bind(&endpoint)
+            .unwrap()
+            .with_policy(|peer: &PeerCredentials| peer.pid == Some(0));
+
+        let (mut read, _write) = Socket::connect(&endpoint).await.unwrap().into_split();
+        let accepting = tokio::time::timeout(Duration::from_millis(50), listener.accept());
+        assert!(accepting.await.is_err());
+
+        // Dropped without ever being read from
+        let mut buffer = [0; 1];
+        assert_eq!(read.read(&mut buffer).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn creates_private_socket_directories() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempPath::new("transport");
+        let path = dir.join("nested/central.sock");
+
+        let listener = Listener::bind(&Endpoint::Unix(path.clone())).unwrap();
+        let mode = fs::metadata(path.parent().unwrap())
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        drop(listener);
+    }
+}
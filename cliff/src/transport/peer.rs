@@ -0,0 +1,133 @@
+use std::{collections::HashSet, io};
+
+use tokio::net::UnixStream;
+
+/// Who's on the other end of a Unix socket, as the kernel reports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerCredentials {
+    pub uid: u32,
+    pub gid: u32,
+    /// Only reported on Linux.
+    pub pid: Option<u32>,
+}
+
+impl PeerCredentials {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) fn of(stream: &UnixStream) -> io::Result<Self> {
+        use std::{mem, os::unix::io::AsRawFd};
+
+        let mut credentials = libc::ucred {
+            pid: 0,
+            uid: 0,
+            gid: 0,
+        };
+        let mut length = mem::size_of::<libc::ucred>() as libc::socklen_t;
+
+        // Safe, `credentials` and `length` outlive the call and match what SO_PEERCRED writes
+        let result = unsafe {
+            libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                &mut credentials as *mut libc::ucred as *mut libc::c_void,
+                &mut length,
+            )
+        };
+        if result != 0 || length as usize != mem::size_of::<libc::ucred>() {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            uid: credentials.uid,
+            gid: credentials.gid,
+            pid: Some(credentials.pid as u32),
+        })
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub(crate) fn of(stream: &UnixStream) -> io::Result<Self> {
+        let credentials = stream.peer_cred()?;
+
+        Ok(Self {
+            uid: credentials.uid,
+            gid: credentials.gid,
+            pid: None,
+        })
+    }
+}
+
+/// Decides which local peers a `Listener` lets in.
+///
+/// Rejected peers are disconnected as soon as they're accepted, before anything they
+/// sent is read.
+pub trait Authorize: Send + Sync + 'static {
+    fn authorize(&self, peer: &PeerCredentials) -> bool;
+}
+
+impl<F> Authorize for F
+where
+    F: Fn(&PeerCredentials) -> bool + Send + Sync + 'static,
+{
+    fn authorize(&self, peer: &PeerCredentials) -> bool {
+        self(peer)
+    }
+}
+
+/// Only lets in processes running as the same user. The default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SameUser;
+
+impl Authorize for SameUser {
+    fn authorize(&self, peer: &PeerCredentials) -> bool {
+        // Safe, `geteuid` can't fail
+        peer.uid == unsafe { libc::geteuid() }
+    }
+}
+
+/// Lets in the users and groups it lists.
+#[derive(Debug, Clone, Default)]
+pub struct Allowlist {
+    uids: HashSet<u32>,
+    gids: HashSet<u32>,
+}
+
+impl Allowlist {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_uid(mut self, uid: u32) -> Self {
+        self.uids.insert(uid);
+
+        self
+    }
+
+    pub fn with_gid(mut self, gid: u32) -> Self {
+        self.gids.insert(gid);
+
+        self
+    }
+}
+
+impl Authorize for Allowlist {
+    fn authorize(&self, peer: &PeerCredentials) -> bool {
+        self.uids.contains(&peer.uid) || self.gids.contains(&peer.gid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reads_peer_credentials() {
+        let (ours, _theirs) = UnixStream::pair().unwrap();
+        let peer = PeerCredentials::of(&ours).unwrap();
+
+        assert_eq!(peer.uid, unsafe { libc::geteuid() });
+        assert_eq!(peer.gid, unsafe { libc::getegid() });
+        assert!(SameUser.authorize(&peer));
+        assert!(Allowlist::new().with_gid(peer.gid).authorize(&peer));
+        assert!(!Allowlist::new().with_uid(peer.uid + 1).authorize(&peer));
+    }
+}
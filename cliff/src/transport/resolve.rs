@@ -0,0 +1,202 @@
+use std::{
+    env, fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use failure::{format_err, Error, ResultExt};
+
+use serde::Deserialize;
+
+use super::Endpoint;
+
+/// Overrides the endpoint every piece of central uses, as `unix:<path>`, `tcp:<addr>` or a path.
+pub const ENDPOINT_VAR: &str = "CENTRAL_ENDPOINT";
+/// Points at a config file other than `$XDG_CONFIG_HOME/central/config.toml`.
+pub const CONFIG_VAR: &str = "CENTRAL_CONFIG";
+
+#[derive(Debug, Default, Deserialize)]
+struct Config {
+    endpoint: Option<String>,
+}
+
+impl FromStr for Endpoint {
+    type Err = Error;
+
+    fn from_str(endpoint: &str) -> Result<Self, Error> {
+        if let Some(addr) = endpoint.strip_prefix("tcp:") {
+            let addr = addr
+                .parse::<SocketAddr>()
+                .with_context(|_| format!("Invalid TCP address: {}", addr))?;
+
+            return Ok(Endpoint::Tcp(addr));
+        }
+
+        let path = endpoint.strip_prefix("unix:").unwrap_or(endpoint);
+        if path.is_empty() {
+            return Err(format_err!("Missing socket path in endpoint: {}", endpoint));
+        }
+
+        Ok(Endpoint::Unix(path.into()))
+    }
+}
+
+impl Endpoint {
+    /// Figures out where the station is, the first of these winning:
+    ///
+    /// 1. `explicit`, usually from a command line argument
+    /// 2. the `CENTRAL_ENDPOINT` env var
+    /// 3. `endpoint` in the config file
+    /// 4. `central/central.sock` in `$XDG_RUNTIME_DIR`
+    /// 5. `$HOME/.central/central.sock`
+    pub fn resolve(explicit: Option<&str>) -> Result<Self, Error> {
+        resolve_with(explicit, |name| env::var(name).ok())
+    }
+}
+
+fn resolve_with<F>(explicit: Option<&str>, var: F) -> Result<Endpoint, Error>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    if let Some(endpoint) = explicit {
+        return endpoint.parse();
+    }
+    if let Some(endpoint) = var(ENDPOINT_VAR) {
+        return endpoint
+            .parse::<Endpoint>()
+            .with_context(|_| format!("Invalid {}", ENDPOINT_VAR))
+            .map_err(Error::from);
+    }
+    if let Some(path) = config_path(&var) {
+        if let Some(endpoint) = read_config(&path)?.endpoint {
+            return endpoint
+                .parse::<Endpoint>()
+                .with_context(|_| format!("Invalid endpoint in {}", path.display()))
+                .map_err(Error::from);
+        }
+    }
+    if let Some(runtime_dir) = var("XDG_RUNTIME_DIR") {
+        return Ok(Endpoint::Unix(
+            Path::new(&runtime_dir).join("central/central.sock"),
+        ));
+    }
+
+    let home = var("HOME").ok_or_else(|| format_err!("Couldn't retrieve HOME env var"))?;
+    Ok(Endpoint::Unix(
+        Path::new(&home).join(".central/central.sock"),
+    ))
+}
+
+fn config_path<F>(var: &F) -> Option<PathBuf>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    if let Some(path) = var(CONFIG_VAR) {
+        return Some(path.into());
+    }
+
+    let config_dir = match var("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(&var("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("central/config.toml"))
+}
+
+fn read_config(path: &Path) -> Result<Config, Error> {
+    // Not having a config file at all is fine
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(_) if !path.exists() => return Ok(Config::default()),
+        Err(e) => return Err(Error::from(e)),
+    };
+
+    toml::from_str(&contents)
+        .with_context(|_| format!("Invalid config file: {}", path.display()))
+        .map_err(Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use crate::testing::TempPath;
+
+    fn resolve(explicit: Option<&str>, vars: &[(&str, &str)]) -> Endpoint {
+        let vars: HashMap<_, _> = vars.iter().cloned().collect();
+
+        resolve_with(explicit, |name| {
+            vars.get(name).map(|value| value.to_string())
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn parses_endpoints() {
+        assert_eq!(
+            "tcp:127.0.0.1:4000".parse::<Endpoint>().unwrap(),
+            Endpoint::Tcp("127.0.0.1:4000".parse().unwrap())
+        );
+        assert_eq!(
+            "unix:/tmp/central.sock".parse::<Endpoint>().unwrap(),
+            Endpoint::Unix("/tmp/central.sock".into())
+        );
+        assert_eq!(
+            "/tmp/central.sock".parse::<Endpoint>().unwrap(),
+            Endpoint::Unix("/tmp/central.sock".into())
+        );
+        assert!("tcp:nowhere".parse::<Endpoint>().is_err());
+        assert!("unix:".parse::<Endpoint>().is_err());
+    }
+
+    #[test]
+    fn resolves_in_order_of_precedence() {
+        let path = TempPath::new("config.toml");
+        fs::write(&path, "endpoint = \"tcp:127.0.0.1:4000\"\n").unwrap();
+        let config = path.to_str().unwrap();
+
+        let mut vars = vec![("HOME", "/home/cat")];
+        assert_eq!(
+            resolve(None, &vars),
+            Endpoint::Unix("/home/cat/.central/central.sock".into())
+        );
+
+        vars.push(("XDG_RUNTIME_DIR", "/run/user/1000"));
+        assert_eq!(
+            resolve(None, &vars),
+            Endpoint::Unix("/run/user/1000/central/central.sock".into())
+        );
+
+        vars.push((CONFIG_VAR, config));
+        assert_eq!(
+            resolve(None, &vars),
+            Endpoint::Tcp("127.0.0.1:4000".parse().unwrap())
+        );
+
+        vars.push((ENDPOINT_VAR, "/tmp/from-env.sock"));
+        assert_eq!(
+            resolve(None, &vars),
+            Endpoint::Unix("/tmp/from-env.sock".into())
+        );
+
+        assert_eq!(
+            resolve(Some("unix:/tmp/explicit.sock"), &vars),
+            Endpoint::Unix("/tmp/explicit.sock".into())
+        );
+    }
+
+    #[test]
+    fn missing_config_files_are_skipped() {
+        let vars = [
+            ("HOME", "/home/cat"),
+            ("XDG_CONFIG_HOME", "/nonexistent/config"),
+        ];
+
+        assert_eq!(
+            resolve(None, &vars),
+            Endpoint::Unix("/home/cat/.central/central.sock".into())
+        );
+    }
+}
@@ -0,0 +1,7 @@
+#[test]
+fn derives_messages_and_registries() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/ui/message_*.rs");
+    cases.pass("tests/ui/registered_*.rs");
+    cases.compile_fail("tests/ui/bad_*.rs");
+}
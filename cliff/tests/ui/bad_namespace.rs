@@ -0,0 +1,15 @@
+use cliff::Message;
+
+#[derive(Message)]
+#[namespace(shelter)]
+struct Unquoted;
+
+#[derive(Message)]
+#[namespace("shelter:cats")]
+struct Nested;
+
+#[derive(Message)]
+#[namespace("")]
+struct Empty;
+
+fn main() {}
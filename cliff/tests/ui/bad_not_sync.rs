@@ -0,0 +1,14 @@
+use std::cell::Cell;
+
+use cliff::Message;
+
+#[derive(Message)]
+struct Counted(Cell<u32>);
+
+fn message_type<M: Message>() -> &'static str {
+    M::message_type()
+}
+
+fn main() {
+    message_type::<Counted>();
+}
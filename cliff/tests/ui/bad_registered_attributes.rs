@@ -0,0 +1,14 @@
+use cliff::{Actor, Registered};
+
+#[derive(Registered)]
+struct Silent;
+
+impl Actor for Silent {}
+
+#[derive(Registered)]
+#[handles(Subscribe Unsubscribe)]
+struct Garbled;
+
+impl Actor for Garbled {}
+
+fn main() {}
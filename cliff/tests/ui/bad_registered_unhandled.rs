@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+
+use cliff::{Actor, Handler, Message, Registered, Request, Responder};
+
+#[derive(Registered)]
+#[handles(Subscribe, Unsubscribe)]
+#[answers(Total, Count)]
+struct Server;
+
+impl Actor for Server {}
+
+#[derive(Message, Serialize, Deserialize)]
+struct Subscribe(usize);
+
+#[derive(Message, Serialize, Deserialize)]
+struct Unsubscribe(usize);
+
+#[derive(Message, Serialize, Deserialize)]
+struct Count;
+
+impl Request for Count {
+    type Result = usize;
+}
+
+#[derive(Message, Serialize, Deserialize)]
+struct Total;
+
+impl Request for Total {
+    type Result = usize;
+}
+
+impl Handler<Subscribe> for Server {
+    fn handle(&mut self, _: &mut Subscribe) {}
+}
+
+impl Responder<Total> for Server {
+    fn respond(&mut self, _: &mut Total) -> usize {
+        0
+    }
+}
+
+fn main() {}
@@ -0,0 +1,19 @@
+use cliff::Message;
+
+#[derive(Message)]
+#[namespace("shelter")]
+enum Update {
+    Adopted(usize),
+    Abandoned { id: usize },
+    Closed,
+}
+
+fn main() {
+    assert_eq!(Update::message_type(), "shelter:Update");
+
+    let _ = [
+        Update::Adopted(0),
+        Update::Abandoned { id: 1 },
+        Update::Closed,
+    ];
+}
@@ -0,0 +1,29 @@
+use std::{fmt::Debug, marker::PhantomData};
+
+use cliff::Message;
+
+#[derive(Message)]
+#[namespace("shelter")]
+struct Listing<'a, T: Debug, const N: usize>
+where
+    T: Clone,
+{
+    items: [&'a T; N],
+}
+
+#[derive(Message)]
+struct Marker<T>(PhantomData<fn() -> T>);
+
+fn message_type<M: Message>(_: &M) -> &'static str {
+    M::message_type()
+}
+
+fn main() {
+    let cat = String::from("Tom");
+    let listing = Listing { items: [&cat] };
+    assert_eq!(message_type(&listing), "shelter:Listing");
+
+    // `Rc` isn't `Send`, but a marker only names it
+    let marker = Marker::<std::rc::Rc<u8>>(PhantomData);
+    assert_eq!(message_type(&marker), "Marker");
+}
@@ -0,0 +1,23 @@
+use cliff::Message;
+
+#[derive(Message)]
+struct Subscribe {
+    id: usize,
+}
+
+#[derive(Message)]
+#[namespace("shelter")]
+struct Adopt {
+    id: usize,
+}
+
+#[derive(Message)]
+struct Stop;
+
+fn main() {
+    assert_eq!(Subscribe::message_type(), "Subscribe");
+    assert_eq!(Adopt::message_type(), "shelter:Adopt");
+    assert_eq!(Stop::message_type(), "Stop");
+
+    let _ = Subscribe { id: 0 }.id + Adopt { id: 1 }.id;
+}
@@ -0,0 +1,13 @@
+use cliff::Message;
+
+#[derive(Message)]
+#[namespace("shelter")]
+struct Pet(usize, String);
+
+fn main() {
+    const MESSAGE_TYPE: fn() -> &'static str = Pet::message_type;
+
+    assert_eq!(MESSAGE_TYPE(), "shelter:Pet");
+
+    let _ = Pet(0, String::new());
+}
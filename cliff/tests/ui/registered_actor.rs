@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+
+use cliff::{Actor, Handler, Message, Registered, Request, Responder};
+
+#[derive(Default, Registered)]
+#[handles(Add, Reset)]
+#[answers(Count)]
+struct Counter(u32);
+
+impl Actor for Counter {}
+
+#[derive(Message, Serialize, Deserialize)]
+#[namespace("counter")]
+struct Add(u32);
+
+#[derive(Message, Serialize, Deserialize)]
+#[namespace("counter")]
+struct Reset;
+
+#[derive(Message, Serialize, Deserialize)]
+#[namespace("counter")]
+struct Count;
+
+impl Request for Count {
+    type Result = u32;
+}
+
+impl Handler<Add> for Counter {
+    fn handle(&mut self, message: &mut Add) {
+        self.0 += message.0;
+    }
+}
+
+impl Handler<Reset> for Counter {
+    fn handle(&mut self, _: &mut Reset) {
+        self.0 = 0;
+    }
+}
+
+impl Responder<Count> for Counter {
+    fn respond(&mut self, _: &mut Count) -> u32 {
+        self.0
+    }
+}
+
+fn main() {
+    let registry = Counter::registry();
+
+    let value = registry.encode(&Add(3)).unwrap();
+    assert_eq!(value.as_array().unwrap()[0].as_str(), Some("counter:Add"));
+    assert!(registry.decode(value).is_ok());
+    assert!(registry.encode(&Reset).is_ok());
+
+    // Requests only decode from envelopes
+    let request = rmpv::Value::Array(vec!["counter:Count".into(), rmpv::Value::Nil]);
+    assert!(registry.decode(request).is_err());
+}
@@ -2,41 +2,117 @@ extern crate proc_macro;
 
 use proc_macro::TokenStream;
 
-use quote::quote;
-use syn::{parse_macro_input, Attribute, DeriveInput};
+use proc_macro2::Span;
+use quote::{quote, quote_spanned};
+use syn::{
+    parse_macro_input, parse_quote, punctuated::Punctuated, spanned::Spanned, Attribute,
+    DeriveInput, Error, LitStr, Token, Type,
+};
 
+/// Implements `cliff::Message`, its `message_type` being `Name` or `NameSpace:Name`.
+///
+/// Generic messages share one type for every set of parameters, and are only
+/// messages when they're `Send + Sync`.
+///
 /// Usage:
-/// ```
+/// ```ignore
 /// #[derive(Message)]
 /// #[namespace("NameSpace")] // Optional Argument
 /// struct MessageStruct{};
 /// ```
 #[proc_macro_derive(Message, attributes(namespace))]
 pub fn message_macro(input: TokenStream) -> TokenStream {
-    let input = parse_macro_input!(input as DeriveInput);
+    let mut input = parse_macro_input!(input as DeriveInput);
+
+    let ns = input
+        .attrs
+        .iter()
+        .find(|&attr| attr.path.is_ident("namespace"));
+
+    let message_type = match get_namespace(ns) {
+        Ok(Some(ns)) => format!("{}:{}", ns, input.ident),
+        Ok(None) => input.ident.to_string(),
+        Err(e) => return TokenStream::from(e.to_compile_error()),
+    };
+
+    input
+        .generics
+        .make_where_clause()
+        .predicates
+        .push(parse_quote! { Self: Send + Sync });
 
-    let ns = input.attrs.iter().find(|&attr| {
-        if let Some(ident) = attr.path.get_ident() {
-            ident == "namespace"
-        } else {
-            false
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let expanded = quote! {
+        impl #impl_generics ::cliff::Message for #name #ty_generics #where_clause {
+            fn message_type() -> &'static str {
+                #message_type
+            }
         }
-    });
+    };
 
-    let namespace = get_namespace(ns);
+    TokenStream::from(expanded)
+}
 
-    let name = input.ident;
-    let str_ident = name.to_string();
+/// Implements `cliff::Registered`, listing the messages an actor accepts over a connection.
+///
+/// Each type is tagged on the wire with its `message_type`, so they're best
+/// derived as `Message`s. Types in `#[handles(...)]` need a `Handler` impl,
+/// and ones in `#[answers(...)]` a `Responder` impl.
+///
+/// Usage:
+/// ```ignore
+/// #[derive(Registered)]
+/// #[handles(Subscribe, Unsubscribe)]
+/// #[answers(Count)] // Optional Argument
+/// struct Server{};
+/// ```
+#[proc_macro_derive(Registered, attributes(handles, answers))]
+pub fn registered_macro(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
 
-    let as_str = match namespace {
-        Some(ns) => quote! { format!("{}:{}", #ns, #str_ident) },
-        None => quote! { #str_ident.to_string() },
+    let messages = match get_types(&input.attrs, "handles") {
+        Ok(messages) => messages,
+        Err(e) => return TokenStream::from(e.to_compile_error()),
     };
+    let requests = match get_types(&input.attrs, "answers") {
+        Ok(requests) => requests,
+        Err(e) => return TokenStream::from(e.to_compile_error()),
+    };
+
+    if messages.is_empty() && requests.is_empty() {
+        let e = Error::new(
+            Span::call_site(),
+            "expected the accepted messages in `#[handles(...)]` or `#[answers(...)]`",
+        );
+        return TokenStream::from(e.to_compile_error());
+    }
+
+    // Spanned on each type, so a missing impl is reported where it's listed
+    let registrations = messages
+        .iter()
+        .map(|ty| {
+            quote_spanned! { ty.span()=>
+                registry.register::<#ty>(<#ty as ::cliff::Message>::message_type());
+            }
+        })
+        .chain(requests.iter().map(|ty| {
+            quote_spanned! { ty.span()=>
+                registry.register_request::<#ty>(<#ty as ::cliff::Message>::message_type());
+            }
+        }));
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
 
     let expanded = quote! {
-        impl Message for #name {
-            fn message_type(&self) -> String {
-                #as_str
+        impl #impl_generics ::cliff::Registered for #name #ty_generics #where_clause {
+            fn registry() -> ::cliff::Registry<Self> {
+                let mut registry = ::cliff::Registry::new();
+                #(#registrations)*
+
+                registry
             }
         }
     };
@@ -44,28 +120,30 @@ pub fn message_macro(input: TokenStream) -> TokenStream {
     TokenStream::from(expanded)
 }
 
-fn get_namespace(attr: Option<&Attribute>) -> Option<proc_macro2::TokenStream> {
-    if let Some(namespace_attr) = attr {
-        let tokens = namespace_attr.tokens.clone();
-        return match tokens
-            .into_iter()
-            .filter_map(|tree| match tree {
-                proc_macro2::TokenTree::Group(group) => Some(group.stream()),
-                _ => None,
-            })
-            .flatten()
-            .filter_map(|tree| match tree {
-                proc_macro2::TokenTree::Literal(lit) => Some(lit),
-                _ => None,
-            })
-            .nth(0)
-        {
-            Some(lit) => Some(proc_macro2::TokenStream::from(
-                proc_macro2::TokenTree::from(lit),
-            )),
-            None => None,
-        };
+fn get_types(attrs: &[Attribute], name: &str) -> Result<Vec<Type>, Error> {
+    let mut types = vec![];
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident(name)) {
+        let listed = attr.parse_args_with(Punctuated::<Type, Token![,]>::parse_terminated)?;
+        types.extend(listed);
+    }
+
+    Ok(types)
+}
+
+fn get_namespace(attr: Option<&Attribute>) -> Result<Option<String>, Error> {
+    let attr = match attr {
+        Some(attr) => attr,
+        None => return Ok(None),
+    };
+
+    let namespace = attr.parse_args::<LitStr>()?;
+    let value = namespace.value();
+    if value.is_empty() || value.contains(':') {
+        return Err(Error::new(
+            namespace.span(),
+            "namespace must be non-empty and can't contain `:`",
+        ));
     }
 
-    None
+    Ok(Some(value))
 }